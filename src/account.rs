@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use crate::money::Money;
+use crate::order_book::{OrderType, Side};
+use crate::client::ClientStockPreference;
+
+// Every client opens with this much cash and this many shares of each listed stock
+pub const INITIAL_CASH: Money = Money::from_ringgit(1_000_000);
+pub const INITIAL_HOLDING: u64 = 1_000;
+
+// Market and stop buys have no price of their own, so reserve the last price plus this margin (percent)
+const PRICE_BUFFER_PERCENT: i64 = 10;
+
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub quantity: u64,
+    pub reserved: u64,
+    pub average_cost: Money,
+}
+
+// Cash or shares set aside for an order that has not finished yet
+#[derive(Debug, Clone)]
+struct Reservation {
+    stock_symbol: String,
+    buy_or_sell: Side,
+    price: Money,
+    quantity: u64,
+}
+
+// Client's cash and holdings as seen by their broker
+#[derive(Debug, Clone)]
+pub struct ClientAccount {
+    pub client_number: String,
+    pub cash: Money,
+    pub reserved_cash: Money,
+    pub positions: HashMap<String, Position>,
+    pub realised_pnl: Money,
+    reservations: HashMap<String, Reservation>,
+}
+
+impl ClientAccount {
+    // Opening holdings are booked at the given prices
+    pub fn new(client_number: String, opening_prices: &HashMap<String, Money>) -> ClientAccount {
+        let positions = opening_prices.iter()
+            .map(|(symbol, price)| (symbol.clone(), Position {
+                quantity: INITIAL_HOLDING,
+                reserved: 0,
+                average_cost: *price,
+            }))
+            .collect();
+
+        ClientAccount {
+            client_number,
+            cash: INITIAL_CASH,
+            reserved_cash: Money::ZERO,
+            positions,
+            realised_pnl: Money::ZERO,
+            reservations: HashMap::new(),
+        }
+    }
+
+    pub fn buying_power(&self) -> Money {
+        self.cash - self.reserved_cash
+    }
+
+    pub fn available_shares(&self, stock_symbol: &str) -> u64 {
+        self.positions.get(stock_symbol)
+            .map(|position| position.quantity - position.reserved)
+            .unwrap_or(0)
+    }
+
+    // Set aside cash for a buy or shares for a sell, or explain why the client cannot afford it
+    pub fn reserve(&mut self, client_preference: &ClientStockPreference, last_price: Option<Money>) -> Result<(), String> {
+        self.reserve_working(client_preference, 0, last_price)
+    }
+
+    // Swap an order's reservation for one matching its new price and quantity
+    // The quantity is the order's new total, shares already filled on it need nothing set aside
+    pub fn re_reserve(&mut self, client_preference: &ClientStockPreference, filled: u64, last_price: Option<Money>) -> Result<(), String> {
+        let previous = self.reservations.remove(&client_preference.order_id);
+
+        if let Some(previous) = &previous {
+            self.apply_reservation(previous, false);
+        }
+
+        let result = self.reserve_working(client_preference, filled, last_price);
+
+        // Put the old reservation back if the new one does not fit
+        if let (Err(_), Some(previous)) = (&result, previous) {
+            self.apply_reservation(&previous, true);
+            self.reservations.insert(client_preference.order_id.clone(), previous);
+        }
+
+        result
+    }
+
+    // Check a reservation would fit without making it
+    pub fn can_reserve(&self, client_preference: &ClientStockPreference, filled: u64, last_price: Option<Money>) -> Result<(), String> {
+        let mut account = self.clone();
+
+        account.re_reserve(client_preference, filled, last_price)
+    }
+
+    // Reserve for whatever of the order is not yet filled
+    fn reserve_working(&mut self, client_preference: &ClientStockPreference, filled: u64, last_price: Option<Money>) -> Result<(), String> {
+        let reservation = self.reservation_for(client_preference, client_preference.quantity.saturating_sub(filled), last_price)?;
+
+        self.apply_reservation(&reservation, true);
+        self.reservations.insert(client_preference.order_id.clone(), reservation);
+
+        Ok(())
+    }
+
+    // Give back whatever is still reserved once the order is finished
+    pub fn release(&mut self, order_id: &str) {
+        if let Some(reservation) = self.reservations.remove(order_id) {
+            self.apply_reservation(&reservation, false);
+        }
+    }
+
+    // Book a fill against the account
+    pub fn apply_fill(&mut self, order_id: &str, quantity: u64, price: Money) {
+        let reservation = match self.reservations.get_mut(order_id) {
+            Some(reservation) => reservation,
+            None => return,
+        };
+
+        let filled = quantity.min(reservation.quantity);
+        reservation.quantity -= filled;
+
+        let stock_symbol = reservation.stock_symbol.clone();
+        let reserved_price = reservation.price;
+        let is_buy = reservation.buy_or_sell == Side::Buy;
+
+        let position = self.positions.entry(stock_symbol).or_default();
+
+        if is_buy {
+            self.reserved_cash -= reserved_price * filled;
+            self.cash -= price * quantity;
+
+            let total_cost = position.average_cost * position.quantity + price * quantity;
+            position.quantity += quantity;
+            position.average_cost = total_cost / position.quantity;
+        } else {
+            position.reserved -= filled;
+            position.quantity -= quantity.min(position.quantity);
+            self.cash += price * quantity;
+            self.realised_pnl += (price - position.average_cost) * quantity;
+        }
+    }
+
+    // Shares of a stock the client's other open buys are still waiting on
+    pub fn reserved_buys(&self, stock_symbol: &str, except_order_id: &str) -> u64 {
+        self.reservations.iter()
+            .filter(|(order_id, reservation)| order_id.as_str() != except_order_id && reservation.buy_or_sell == Side::Buy && reservation.stock_symbol == stock_symbol)
+            .map(|(_, reservation)| reservation.quantity)
+            .sum()
+    }
+
+    // Cash plus holdings at the given prices, holdings without a price count at cost
+    pub fn equity(&self, prices: &HashMap<String, Money>) -> Money {
+        let holdings: Money = self.positions.iter()
+            .map(|(symbol, position)| prices.get(symbol).copied().unwrap_or(position.average_cost) * position.quantity)
+            .sum();
+
+        self.cash + holdings
+    }
+
+    // Gain or loss on open positions at the given prices
+    pub fn unrealised_pnl(&self, last_prices: &HashMap<String, Money>) -> Money {
+        self.positions.iter()
+            .filter_map(|(symbol, position)| {
+                last_prices.get(symbol).map(|price| (*price - position.average_cost) * position.quantity)
+            })
+            .sum()
+    }
+
+    fn reservation_for(&self, client_preference: &ClientStockPreference, quantity: u64, last_price: Option<Money>) -> Result<Reservation, String> {
+
+        if client_preference.buy_or_sell == Side::Buy {
+            let price = match (&client_preference.order_type, last_price) {
+                (OrderType::Limit { price }, _) => *price,
+                (OrderType::StopLimit { limit_price, .. }, _) => *limit_price,
+                (OrderType::Stop { stop_price }, _) => stop_price.scale(100 + PRICE_BUFFER_PERCENT, 100),
+                (OrderType::Market, Some(last_price)) => last_price.scale(100 + PRICE_BUFFER_PERCENT, 100),
+                (OrderType::Market, None) => return Err(format!("No price known for {}", client_preference.stock_symbol)),
+            };
+
+            let cost = Money::value_of(price, quantity)
+                .ok_or_else(|| format!("Order for {} {} is too large to value", quantity, client_preference.stock_symbol))?;
+
+            if cost > self.buying_power() {
+                return Err(format!("Insufficient buying power: order needs RM {}, RM {} available", cost, self.buying_power()));
+            }
+
+            Ok(Reservation { stock_symbol: client_preference.stock_symbol.clone(), buy_or_sell: client_preference.buy_or_sell, price, quantity })
+        } else {
+            let available = self.available_shares(&client_preference.stock_symbol);
+
+            if quantity > available {
+                return Err(format!("Insufficient holdings: selling {} {} but only {} available", quantity, client_preference.stock_symbol, available));
+            }
+
+            Ok(Reservation { stock_symbol: client_preference.stock_symbol.clone(), buy_or_sell: client_preference.buy_or_sell, price: Money::ZERO, quantity })
+        }
+    }
+
+    fn apply_reservation(&mut self, reservation: &Reservation, add: bool) {
+        if reservation.buy_or_sell == Side::Buy {
+            let amount = reservation.price * reservation.quantity;
+
+            if add { self.reserved_cash += amount } else { self.reserved_cash -= amount }
+        } else {
+            let position = self.positions.entry(reservation.stock_symbol.clone()).or_default();
+
+            if add { position.reserved += reservation.quantity } else { position.reserved -= reservation.quantity }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Decision;
+    use crate::order_book::TimeInForce;
+    use crate::simulation::Timestamp;
+    use crate::trend::TrendPattern;
+
+    fn limit_order(buy_or_sell: Side, price: Money, quantity: u64) -> ClientStockPreference {
+        ClientStockPreference {
+            order_id: "order".to_string(),
+            client_number: "1".to_string(),
+            stock_symbol: "MYEG".to_string(),
+            min_price: price,
+            trend: TrendPattern::default(),
+            buy_sell_decision: Decision::Symbol,
+            indicator_condition: None,
+            trigger: None,
+            buy_or_sell,
+            order_type: OrderType::Limit { price },
+            time_in_force: TimeInForce::Day,
+            quantity,
+            created_at: Timestamp::default(),
+        }
+    }
+
+    fn account() -> ClientAccount {
+        ClientAccount::new("1".to_string(), &HashMap::from([("MYEG".to_string(), Money::from_ringgit(100))]))
+    }
+
+    #[test]
+    fn replacing_a_partly_filled_sell_reserves_only_what_is_left() {
+        let mut account = account();
+        let order = limit_order(Side::Sell, Money::from_ringgit(100), INITIAL_HOLDING);
+
+        account.reserve(&order, None).unwrap();
+        account.apply_fill("order", 400, Money::from_ringgit(100));
+
+        assert_eq!(account.positions["MYEG"].quantity, INITIAL_HOLDING - 400);
+        assert_eq!(account.available_shares("MYEG"), 0);
+
+        // Same total at a new price, 600 shares are still working
+        let replaced = limit_order(Side::Sell, Money::from_ringgit(101), INITIAL_HOLDING);
+
+        assert_eq!(account.can_reserve(&replaced, 400, None), Ok(()));
+        account.re_reserve(&replaced, 400, None).unwrap();
+
+        assert_eq!(account.positions["MYEG"].reserved, INITIAL_HOLDING - 400);
+        assert_eq!(account.available_shares("MYEG"), 0);
+    }
+
+    #[test]
+    fn replacing_a_partly_filled_buy_reserves_cash_for_what_is_left() {
+        let mut account = account();
+        let order = limit_order(Side::Buy, Money::from_ringgit(100), 1_000);
+
+        account.reserve(&order, None).unwrap();
+        account.apply_fill("order", 300, Money::from_ringgit(100));
+
+        assert_eq!(account.reserved_cash, Money::from_ringgit(70_000));
+
+        let replaced = limit_order(Side::Buy, Money::from_ringgit(102), 1_000);
+        account.re_reserve(&replaced, 300, None).unwrap();
+
+        assert_eq!(account.reserved_cash, Money::from_ringgit(71_400));
+        assert_eq!(account.cash, INITIAL_CASH - Money::from_ringgit(30_000));
+    }
+
+    #[test]
+    fn refuses_a_buy_whose_cost_is_too_large_to_hold() {
+        let mut account = account();
+        let order = limit_order(Side::Buy, Money::from_sen(7988), 18446744073709551600);
+
+        assert!(account.reserve(&order, None).is_err());
+        assert_eq!(account.reserved_cash, Money::ZERO);
+    }
+}
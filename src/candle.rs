@@ -0,0 +1,194 @@
+use std::fmt;
+use std::time::Duration;
+use std::collections::{BTreeMap, VecDeque};
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::simulation::Timestamp;
+use crate::stock_exchange::TRADING_DAY_SECONDS;
+
+// Fanout the stock exchange publishes completed bars on
+pub const CANDLE_BROADCAST_EXCHANGE: &str = "candles";
+
+// Length of one bar, each starts on a multiple of its length from the 09:00 open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum BarInterval {
+    #[serde(rename = "1s")]
+    OneSecond,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    // The whole trading day
+    #[serde(rename = "1d")]
+    Daily,
+}
+
+pub fn default_bar_intervals() -> Vec<BarInterval> {
+    vec![BarInterval::OneSecond, BarInterval::OneMinute, BarInterval::FiveMinutes, BarInterval::Daily]
+}
+
+impl BarInterval {
+    pub fn duration(&self) -> Duration {
+        match self {
+            BarInterval::OneSecond => Duration::from_secs(1),
+            BarInterval::OneMinute => Duration::from_secs(60),
+            BarInterval::FiveMinutes => Duration::from_secs(5 * 60),
+            BarInterval::Daily => Duration::from_secs(TRADING_DAY_SECONDS),
+        }
+    }
+
+    // Start of the bar a point of simulated time falls in
+    pub fn bar_start(&self, at: Timestamp) -> Timestamp {
+        let length = self.duration().as_millis() as u64;
+        let elapsed = at.since(Timestamp::default()).as_millis() as u64;
+
+        Timestamp::from_duration(Duration::from_millis(elapsed / length * length))
+    }
+}
+
+impl fmt::Display for BarInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BarInterval::OneSecond => "1s",
+            BarInterval::OneMinute => "1m",
+            BarInterval::FiveMinutes => "5m",
+            BarInterval::Daily => "1d",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// Open, high, low, close and volume of one stock over one bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub stock_symbol: String,
+    pub interval: BarInterval,
+    pub start: Timestamp,
+    pub open: Money,
+    pub high: Money,
+    pub low: Money,
+    pub close: Money,
+    // Shares traded, price updates without a trade add none
+    pub volume: u64,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(stock_symbol: &str, interval: BarInterval, start: Timestamp, price: Money) -> Candle {
+        Candle {
+            stock_symbol: stock_symbol.to_string(),
+            interval,
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0,
+            trades: 0,
+        }
+    }
+
+    pub fn end(&self) -> Timestamp {
+        self.start.after(self.interval.duration())
+    }
+
+    fn add(&mut self, price: Money, volume: u64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+
+        if volume > 0 {
+            self.volume += volume;
+            self.trades += 1;
+        }
+    }
+}
+
+// Bars still open at the stock exchange, keyed by symbol and interval so they complete in the same order every run
+#[derive(Debug)]
+pub struct CandleAggregator {
+    intervals: Vec<BarInterval>,
+    open_bars: BTreeMap<(String, BarInterval), Candle>,
+}
+
+impl CandleAggregator {
+    pub fn new(intervals: Vec<BarInterval>) -> CandleAggregator {
+        CandleAggregator { intervals, open_bars: BTreeMap::new() }
+    }
+
+    // Shortest bar, the others are multiples of it
+    pub fn shortest_interval(&self) -> Option<BarInterval> {
+        self.intervals.iter().min().copied()
+    }
+
+    // Add a trade, or a price move with no volume, bars it falls after come back completed
+    pub fn record(&mut self, stock_symbol: &str, price: Money, volume: u64, at: Timestamp) -> Vec<Candle> {
+        let mut completed = Vec::new();
+
+        for interval in self.intervals.iter() {
+            let start = interval.bar_start(at);
+            let key = (stock_symbol.to_string(), *interval);
+
+            match self.open_bars.get_mut(&key) {
+                Some(bar) if bar.start == start => bar.add(price, volume),
+                _ => {
+                    let mut bar = Candle::new(stock_symbol, *interval, start, price);
+                    bar.add(price, volume);
+
+                    if let Some(previous) = self.open_bars.insert(key, bar) {
+                        completed.push(previous);
+                    }
+                }
+            }
+        }
+
+        completed
+    }
+
+    // Every open bar whose time is up
+    pub fn complete_due(&mut self, now: Timestamp) -> Vec<Candle> {
+        let due: Vec<(String, BarInterval)> = self.open_bars.iter()
+            .filter(|(_, bar)| bar.end() <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        due.into_iter()
+            .filter_map(|key| self.open_bars.remove(&key))
+            .collect()
+    }
+}
+
+// Bars the broker keeps, so also the longest lookback an indicator can have
+pub const BAR_HISTORY_LENGTH: usize = 120;
+
+// Completed bars a broker keeps for each symbol and interval, oldest dropped first
+#[derive(Debug, Default)]
+pub struct BarHistory {
+    capacity: usize,
+    bars: BTreeMap<(String, BarInterval), VecDeque<Candle>>,
+}
+
+impl BarHistory {
+    pub fn new(capacity: usize) -> BarHistory {
+        BarHistory { capacity, bars: BTreeMap::new() }
+    }
+
+    pub fn push(&mut self, candle: Candle) {
+        let bars = self.bars.entry((candle.stock_symbol.clone(), candle.interval)).or_default();
+
+        bars.push_back(candle);
+
+        while bars.len() > self.capacity {
+            bars.pop_front();
+        }
+    }
+
+    // Oldest first
+    pub fn bars(&self, stock_symbol: &str, interval: BarInterval) -> Vec<&Candle> {
+        self.bars.get(&(stock_symbol.to_string(), interval))
+            .map(|bars| bars.iter().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::order_book::OrderType;
+use crate::simulation::Timestamp;
+
+// How far an instrument's price may move before trading in it is halted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriceLimits {
+    // From the reference price, the previous close; orders priced beyond are rejected
+    pub static_percent: i64,
+    // From the last price, in a single price move or trade
+    pub dynamic_percent: i64,
+    pub halt_secs: u64,
+}
+
+impl Default for PriceLimits {
+    fn default() -> PriceLimits {
+        PriceLimits {
+            static_percent: 30,
+            dynamic_percent: 10,
+            halt_secs: 5 * 60,
+        }
+    }
+}
+
+impl PriceLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.static_percent <= 0 || self.static_percent >= 100 {
+            return Err("static price limit must be between 0 and 100 percent".to_string());
+        }
+
+        if self.dynamic_percent <= 0 || self.dynamic_percent > self.static_percent {
+            return Err("dynamic price limit must be positive and no wider than the static limit".to_string());
+        }
+
+        if self.halt_secs == 0 {
+            return Err("price limit halts must last at least 1 second".to_string());
+        }
+
+        Ok(())
+    }
+
+    // Lowest and highest price allowed for the day
+    pub fn static_band(&self, reference_price: Money) -> (Money, Money) {
+        let band = reference_price.scale(self.static_percent, 100);
+
+        (reference_price - band, reference_price + band)
+    }
+
+    // Reason an order is priced outside the static limits, if it is
+    pub fn check_order_price(&self, reference_price: Money, order_type: &OrderType) -> Option<String> {
+        let (lower, upper) = self.static_band(reference_price);
+
+        let prices = match order_type {
+            OrderType::Market => vec![],
+            OrderType::Limit { price } => vec![*price],
+            OrderType::Stop { stop_price } => vec![*stop_price],
+            OrderType::StopLimit { stop_price, limit_price } => vec![*stop_price, *limit_price],
+        };
+
+        prices.into_iter()
+            .find(|price| *price < lower || *price > upper)
+            .map(|price| format!("Price RM {} is outside the static limits of RM {} to RM {}", price, lower, upper))
+    }
+
+    // Reason a move from one price to the next breaks a limit, if it does
+    pub fn check_move(&self, reference_price: Money, from: Money, to: Money) -> Option<String> {
+        let (lower, upper) = self.static_band(reference_price);
+
+        if to <= lower || to >= upper {
+            return Some(format!("RM {} reached the static limit of {}% from RM {}", to, self.static_percent, reference_price));
+        }
+
+        let band = from.scale(self.dynamic_percent, 100);
+
+        if to < from - band || to > from + band {
+            return Some(format!("RM {} moved more than {}% from RM {}", to, self.dynamic_percent, from));
+        }
+
+        None
+    }
+}
+
+// A market-wide halt once the headline index falls this far below the previous close
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerLevel {
+    pub drop_percent: i64,
+    // None halts trading for the rest of the day
+    pub halt_secs: Option<u64>,
+}
+
+// Bursa's three levels
+pub fn bursa_circuit_breakers() -> Vec<CircuitBreakerLevel> {
+    vec![
+        CircuitBreakerLevel { drop_percent: 10, halt_secs: Some(60 * 60) },
+        CircuitBreakerLevel { drop_percent: 15, halt_secs: Some(60 * 60) },
+        CircuitBreakerLevel { drop_percent: 20, halt_secs: None },
+    ]
+}
+
+pub fn validate_circuit_breakers(levels: &[CircuitBreakerLevel]) -> Result<(), String> {
+    if levels.iter().any(|level| level.drop_percent <= 0 || level.drop_percent >= 100) {
+        return Err("circuit breaker drops must be between 0 and 100 percent".to_string());
+    }
+
+    if levels.windows(2).any(|levels| levels[0].drop_percent >= levels[1].drop_percent) {
+        return Err("circuit breaker levels must be in rising drop order".to_string());
+    }
+
+    if levels.iter().any(|level| level.halt_secs == Some(0)) {
+        return Err("circuit breaker halts must last at least 1 second".to_string());
+    }
+
+    Ok(())
+}
+
+// Broadcast on the stock price fanout whenever trading stops or starts again, for one stock or the whole market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HaltNotice {
+    pub exchange: String,
+    // None for a market-wide halt
+    pub stock_symbol: Option<String>,
+    pub halted: bool,
+    pub reason: String,
+    pub at: Timestamp,
+}
+
+#[derive(Debug, Clone)]
+struct Halt {
+    reason: String,
+    // None lasts until the next trading day
+    until: Option<Timestamp>,
+}
+
+// Every halt in force at the stock exchange, notices wait here until they are broadcast
+#[derive(Debug)]
+pub struct TradingHalts {
+    exchange: String,
+    levels: Vec<CircuitBreakerLevel>,
+    // Levels tripped today, each trips once a day
+    tripped: usize,
+    market: Option<Halt>,
+    stocks: BTreeMap<String, Halt>,
+    notices: Vec<HaltNotice>,
+}
+
+impl TradingHalts {
+    pub fn new(exchange: String, levels: Vec<CircuitBreakerLevel>) -> TradingHalts {
+        TradingHalts {
+            exchange,
+            levels,
+            tripped: 0,
+            market: None,
+            stocks: BTreeMap::new(),
+            notices: Vec::new(),
+        }
+    }
+
+    pub fn is_halted(&self, stock_symbol: &str) -> bool {
+        self.market.is_some() || self.stocks.contains_key(stock_symbol)
+    }
+
+    // Reason orders for the stock are refused, if they are
+    pub fn check_order(&self, stock_symbol: &str) -> Option<String> {
+        if let Some(halt) = &self.market {
+            return Some(format!("Market-wide trading halt: {}", halt.reason));
+        }
+
+        self.stocks.get(stock_symbol).map(|halt| format!("Trading in {} is halted: {}", stock_symbol, halt.reason))
+    }
+
+    pub fn halt_stock(&mut self, stock_symbol: &str, reason: String, halt_secs: u64, now: Timestamp) {
+        if self.stocks.contains_key(stock_symbol) {
+            return;
+        }
+
+        let until = now.after(Duration::from_secs(halt_secs));
+
+        self.notify(Some(stock_symbol.to_string()), true, reason.clone(), now);
+        self.stocks.insert(stock_symbol.to_string(), Halt { reason, until: Some(until) });
+    }
+
+    // Trip the highest level the headline index has fallen through, levels are its value now and at the previous close
+    pub fn check_market(&mut self, index_name: &str, value: f64, previous_close: f64, now: Timestamp) {
+        let reached = self.levels.iter()
+            .take_while(|level| value <= previous_close * (100 - level.drop_percent) as f64 / 100.0)
+            .count();
+
+        if reached <= self.tripped {
+            return;
+        }
+
+        self.tripped = reached;
+
+        let level = &self.levels[reached - 1];
+        let until = level.halt_secs.map(|halt_secs| now.after(Duration::from_secs(halt_secs)));
+        let reason = format!("{} fell {}% below the previous close, circuit breaker level {}", index_name, level.drop_percent, reached);
+
+        self.notify(None, true, reason.clone(), now);
+        self.market = Some(Halt { reason, until });
+    }
+
+    // End every halt that has run its time
+    pub fn resume_due(&mut self, now: Timestamp) {
+        if self.market.as_ref().is_some_and(|halt| halt.until.is_some_and(|until| until <= now)) {
+            self.market = None;
+            self.notify(None, false, "market-wide halt ended".to_string(), now);
+        }
+
+        let resumed: Vec<String> = self.stocks.iter()
+            .filter(|(_, halt)| halt.until.is_some_and(|until| until <= now))
+            .map(|(stock_symbol, _)| stock_symbol.clone())
+            .collect();
+
+        for stock_symbol in resumed {
+            self.stocks.remove(&stock_symbol);
+            self.notify(Some(stock_symbol), false, "halt ended".to_string(), now);
+        }
+    }
+
+    // A new trading day starts with no halts and every level armed again
+    pub fn new_day(&mut self, now: Timestamp) {
+        self.tripped = 0;
+
+        if self.market.take().is_some() {
+            self.notify(None, false, "new trading day".to_string(), now);
+        }
+
+        for stock_symbol in std::mem::take(&mut self.stocks).into_keys() {
+            self.notify(Some(stock_symbol), false, "new trading day".to_string(), now);
+        }
+    }
+
+    pub fn take_notices(&mut self) -> Vec<HaltNotice> {
+        std::mem::take(&mut self.notices)
+    }
+
+    fn notify(&mut self, stock_symbol: Option<String>, halted: bool, reason: String, at: Timestamp) {
+        self.notices.push(HaltNotice { exchange: self.exchange.clone(), stock_symbol, halted, reason, at });
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::circuit_breaker::{bursa_circuit_breakers, validate_circuit_breakers, CircuitBreakerLevel};
+use crate::index::{bursa_indices, validate_indices, IndexConfig};
+use crate::risk::RiskLimits;
+use crate::instrument::DEFAULT_INSTRUMENTS_PATH;
+use crate::price_model::FactorLoadings;
+use crate::candle::{default_bar_intervals, BarInterval};
+use crate::trend::MAX_TREND_PATTERN_LENGTH;
+use crate::transport::TransportConfig;
+use crate::simulation::{ClockMode, SimulationConfig};
+
+// Used when no path is given on the command line
+pub const DEFAULT_CONFIG_PATH: &str = "config/market.json";
+
+// Everything main needs to build the market: one exchange, any number of brokers and clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketConfig {
+    #[serde(default)]
+    pub transport: TransportConfig,
+    #[serde(default)]
+    pub simulation: SimulationConfig,
+    pub exchange: ExchangeConfig,
+    pub brokers: Vec<BrokerConfig>,
+    pub clients: Vec<ClientConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExchangeConfig {
+    pub name: String,
+    // Instrument master the stock exchange lists
+    pub instruments_path: String,
+    // How often prices move, each instrument's price model decides by how much
+    pub price_update_interval_secs: u64,
+    // How strongly prices move together with the market and their sector
+    pub factor_loadings: FactorLoadings,
+    // Market-wide halts as the headline index falls below the previous close, mildest first
+    pub circuit_breakers: Vec<CircuitBreakerLevel>,
+    // Indices computed on every price change, the first is the headline index
+    pub indices: Vec<IndexConfig>,
+    // Also compute an index for every sector listed
+    pub sector_indices: bool,
+    // OHLCV bars published for every stock
+    pub bar_intervals: Vec<BarInterval>,
+}
+
+impl Default for ExchangeConfig {
+    fn default() -> ExchangeConfig {
+        ExchangeConfig {
+            name: String::from("Bursa Malaysia"),
+            instruments_path: DEFAULT_INSTRUMENTS_PATH.to_string(),
+            price_update_interval_secs: 4,
+            factor_loadings: FactorLoadings::default(),
+            circuit_breakers: bursa_circuit_breakers(),
+            indices: bursa_indices(),
+            sector_indices: true,
+            bar_intervals: default_bar_intervals(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrokerConfig {
+    pub name: String,
+    // RGB colour of the broker's console output
+    pub colour: [u8; 3],
+    #[serde(default)]
+    pub risk: RiskLimits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientConfig {
+    pub name: String,
+    pub broker: String,
+    #[serde(default)]
+    pub strategy: ClientStrategy,
+}
+
+// How a generated client behaves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientStrategy {
+    // Seconds before the first order and between orders, both inclusive ranges
+    pub first_order_delay_secs: [u64; 2],
+    pub order_interval_secs: [u64; 2],
+    // Orders are between these many board lots
+    pub min_lots: u64,
+    pub max_lots: u64,
+    // Range the client's target price is drawn from
+    pub min_price: Money,
+    pub max_price: Money,
+    // Chance a request changes an open order instead of placing a new one, and that the change is a cancel
+    pub change_probability: f64,
+    pub cancel_probability: f64,
+    // Trend patterns are this many moves long, each at least a size drawn from the percent range, some either way
+    pub trend_length: [usize; 2],
+    pub trend_min_move_percent: [f64; 2],
+    pub trend_wildcard_probability: f64,
+}
+
+impl Default for ClientStrategy {
+    fn default() -> ClientStrategy {
+        ClientStrategy {
+            first_order_delay_secs: [2, 6],
+            order_interval_secs: [15, 20],
+            min_lots: 1,
+            max_lots: 10,
+            min_price: Money::from_ringgit(50),
+            max_price: Money::from_ringgit(150),
+            change_probability: 0.25,
+            cancel_probability: 0.5,
+            trend_length: [2, 3],
+            trend_min_move_percent: [0.0, 1.0],
+            trend_wildcard_probability: 0.1,
+        }
+    }
+}
+
+impl MarketConfig {
+    pub fn load(path: &str) -> Result<MarketConfig, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+
+        let config: MarketConfig = serde_json::from_str(&contents).map_err(|err| format!("Cannot parse {}: {}", path, err))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // Catch mistakes that would otherwise show up as silent queues
+    fn validate(&self) -> Result<(), String> {
+        // RabbitMQ delivers in its own order
+        if self.simulation.deterministic && !matches!(self.transport, TransportConfig::InProcess) {
+            return Err("Deterministic simulation needs the in_process transport".to_string());
+        }
+
+        match self.simulation.clock {
+            ClockMode::Accelerated { speed } if !speed.is_finite() || speed <= 0.0 => {
+                return Err("Accelerated clock needs a positive speed".to_string());
+            }
+            // Without the scheduler nothing knows when everyone is idle
+            ClockMode::AsFastAsPossible if !self.simulation.deterministic => {
+                return Err("as_fast_as_possible clock needs deterministic simulation".to_string());
+            }
+            _ => {}
+        }
+
+        self.exchange.factor_loadings.validate()?;
+        validate_circuit_breakers(&self.exchange.circuit_breakers)?;
+        validate_indices(&self.exchange.indices)?;
+
+        let mut bar_intervals = HashSet::new();
+
+        if let Some(interval) = self.exchange.bar_intervals.iter().find(|interval| !bar_intervals.insert(**interval)) {
+            return Err(format!("Bar interval {} is configured more than once", interval));
+        }
+
+        if self.simulation.duration_secs == Some(0) {
+            return Err("Simulation duration must be at least 1 second".to_string());
+        }
+
+        if self.exchange.price_update_interval_secs == 0 {
+            return Err("Prices need an update interval of at least 1 second".to_string());
+        }
+
+        let mut broker_names = HashSet::new();
+
+        for broker in self.brokers.iter() {
+            if !broker_names.insert(broker.name.as_str()) {
+                return Err(format!("Broker {} is configured more than once", broker.name));
+            }
+        }
+
+        let mut client_names = HashSet::new();
+
+        for client in self.clients.iter() {
+            if !client_names.insert(client.name.as_str()) {
+                return Err(format!("Client {} is configured more than once", client.name));
+            }
+
+            if !broker_names.contains(client.broker.as_str()) {
+                return Err(format!("Client {} uses unknown broker {}", client.name, client.broker));
+            }
+
+            let strategy = &client.strategy;
+
+            if strategy.min_lots == 0 || strategy.min_lots > strategy.max_lots {
+                return Err(format!("Client {} needs 0 < min_lots <= max_lots", client.name));
+            }
+
+            if strategy.min_price <= Money::ZERO || strategy.min_price > strategy.max_price {
+                return Err(format!("Client {} needs 0 < min_price <= max_price", client.name));
+            }
+
+            if strategy.first_order_delay_secs[0] > strategy.first_order_delay_secs[1] || strategy.order_interval_secs[0] > strategy.order_interval_secs[1] || strategy.order_interval_secs[0] == 0 {
+                return Err(format!("Client {} has an invalid order timing range", client.name));
+            }
+
+            if strategy.trend_length[0] == 0 || strategy.trend_length[0] > strategy.trend_length[1] || strategy.trend_length[1] > MAX_TREND_PATTERN_LENGTH {
+                return Err(format!("Client {} needs 0 < trend_length[0] <= trend_length[1] <= {}", client.name, MAX_TREND_PATTERN_LENGTH));
+            }
+
+            if !strategy.trend_min_move_percent.iter().all(|percent| percent.is_finite() && *percent >= 0.0) || strategy.trend_min_move_percent[0] > strategy.trend_min_move_percent[1] {
+                return Err(format!("Client {} has an invalid trend move range", client.name));
+            }
+
+            if !(0.0..=1.0).contains(&strategy.change_probability) || !(0.0..=1.0).contains(&strategy.cancel_probability) || !(0.0..=1.0).contains(&strategy.trend_wildcard_probability) {
+                return Err(format!("Client {} has a probability outside 0 to 1", client.name));
+            }
+        }
+
+        Ok(())
+    }
+}
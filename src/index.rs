@@ -0,0 +1,230 @@
+use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+
+use crate::instrument::Instrument;
+use crate::simulation::Timestamp;
+
+// Fanout the stock exchange publishes index values on
+pub const INDEX_BROADCAST_EXCHANGE: &str = "market_index";
+
+// How much each constituent counts towards an index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexWeighting {
+    // Price times shares outstanding, large companies move the index most
+    Capitalisation,
+    // Price alone, every share counts the same
+    Price,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexConfig {
+    pub name: String,
+    // Only stocks of this sector, every listed stock when not given
+    #[serde(default)]
+    pub sector: Option<String>,
+    pub weighting: IndexWeighting,
+    // Only the largest stocks by capitalisation at listing, all of them when not given
+    #[serde(default)]
+    pub max_constituents: Option<usize>,
+    // Level the index starts from
+    #[serde(default = "base_value")]
+    pub base_value: f64,
+}
+
+fn base_value() -> f64 {
+    1000.0
+}
+
+// Headline index, the 30 largest listed companies like the FBM KLCI
+pub fn bursa_indices() -> Vec<IndexConfig> {
+    vec![IndexConfig {
+        name: "FBM KLCI".to_string(),
+        sector: None,
+        weighting: IndexWeighting::Capitalisation,
+        max_constituents: Some(30),
+        base_value: base_value(),
+    }]
+}
+
+// One capitalisation-weighted index for every sector listed
+pub fn sector_indices(instruments: &[Instrument]) -> Vec<IndexConfig> {
+    let sectors: BTreeSet<&str> = instruments.iter().map(|instrument| instrument.sector.as_str()).collect();
+
+    sectors.into_iter()
+        .map(|sector| IndexConfig {
+            name: format!("Bursa Malaysia {} Index", sector),
+            sector: Some(sector.to_string()),
+            weighting: IndexWeighting::Capitalisation,
+            max_constituents: None,
+            base_value: base_value(),
+        })
+        .collect()
+}
+
+pub fn validate_indices(indices: &[IndexConfig]) -> Result<(), String> {
+    let mut names = BTreeSet::new();
+
+    for index in indices.iter() {
+        if !names.insert(index.name.as_str()) {
+            return Err(format!("Index {} is configured more than once", index.name));
+        }
+
+        if !index.base_value.is_finite() || index.base_value <= 0.0 {
+            return Err(format!("Index {} needs a positive base value", index.name));
+        }
+
+        if index.max_constituents == Some(0) {
+            return Err(format!("Index {} needs at least 1 constituent", index.name));
+        }
+    }
+
+    Ok(())
+}
+
+// Broadcast on the index fanout whenever an index moves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexValue {
+    pub exchange: String,
+    pub index: String,
+    // The headline index stands for the whole market
+    pub headline: bool,
+    pub value: f64,
+    pub previous_close: f64,
+    pub change_percent: f64,
+    pub at: Timestamp,
+}
+
+impl IndexValue {
+    // Rising or falling since the previous close, None when unchanged
+    pub fn direction(&self) -> Option<bool> {
+        if self.value == self.previous_close {
+            None
+        } else {
+            Some(self.value > self.previous_close)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MarketIndex {
+    config: IndexConfig,
+    headline: bool,
+    // Symbol and weight of every constituent
+    constituents: Vec<(String, f64)>,
+    // Weighted sum of the constituents' prices that puts the index at its base value
+    divisor: f64,
+    value: f64,
+    previous_close: f64,
+}
+
+// Every index computed by the stock exchange, the first configured is the headline index
+#[derive(Debug)]
+pub struct MarketIndices {
+    exchange: String,
+    indices: Vec<MarketIndex>,
+}
+
+impl MarketIndices {
+    // Constituents and divisors are fixed from the instruments' initial prices
+    pub fn new(exchange: String, configs: Vec<IndexConfig>, instruments: &[Instrument]) -> MarketIndices {
+        let indices = configs.into_iter()
+            .enumerate()
+            .filter_map(|(position, config)| {
+                let mut members: Vec<&Instrument> = instruments.iter()
+                    .filter(|instrument| config.sector.as_ref().is_none_or(|sector| *sector == instrument.sector))
+                    .collect();
+
+                // Largest first, symbol order between equals so every run picks the same constituents
+                members.sort_by(|a, b| capitalisation(b).total_cmp(&capitalisation(a)).then_with(|| a.symbol.cmp(&b.symbol)));
+                members.truncate(config.max_constituents.unwrap_or(members.len()));
+
+                if members.is_empty() {
+                    println!("Index {} has no constituents and is not computed", config.name);
+                    return None;
+                }
+
+                let constituents: Vec<(String, f64)> = members.iter()
+                    .map(|instrument| (instrument.symbol.clone(), weight(config.weighting, instrument)))
+                    .collect();
+
+                let divisor = members.iter().zip(constituents.iter())
+                    .map(|(instrument, (_, weight))| instrument.initial_price.to_f64() * weight)
+                    .sum::<f64>() / config.base_value;
+
+                Some(MarketIndex { value: config.base_value, previous_close: config.base_value, headline: position == 0, config, constituents, divisor })
+            })
+            .collect();
+
+        MarketIndices { exchange, indices }
+    }
+
+    // Recompute every index from the latest prices, only indices that moved come back
+    pub fn update(&mut self, price: impl Fn(&str) -> Option<f64>, now: Timestamp) -> Vec<IndexValue> {
+        let mut moved = Vec::new();
+
+        for index in self.indices.iter_mut() {
+            let weighted: Option<f64> = index.constituents.iter()
+                .map(|(stock_symbol, weight)| price(stock_symbol).map(|price| price * weight))
+                .sum();
+
+            // Points to two decimals, as the index is quoted
+            let value = match weighted {
+                Some(weighted) => (weighted / index.divisor * 100.0).round() / 100.0,
+                None => continue,
+            };
+
+            if value == index.value {
+                continue;
+            }
+
+            index.value = value;
+            moved.push(index_value(&self.exchange, index, now));
+        }
+
+        moved
+    }
+
+    // Name and level of the headline index now and at the previous close
+    pub fn headline(&self) -> Option<(&str, f64, f64)> {
+        self.indices.iter()
+            .find(|index| index.headline)
+            .map(|index| (index.config.name.as_str(), index.value, index.previous_close))
+    }
+
+    pub fn values(&self, now: Timestamp) -> Vec<IndexValue> {
+        self.indices.iter()
+            .map(|index| index_value(&self.exchange, index, now))
+            .collect()
+    }
+
+    // Today's close is what tomorrow's changes are measured from
+    pub fn new_day(&mut self) {
+        for index in self.indices.iter_mut() {
+            index.previous_close = index.value;
+        }
+    }
+}
+
+fn capitalisation(instrument: &Instrument) -> f64 {
+    instrument.initial_price.to_f64() * instrument.shares_outstanding as f64
+}
+
+fn weight(weighting: IndexWeighting, instrument: &Instrument) -> f64 {
+    match weighting {
+        IndexWeighting::Capitalisation => instrument.shares_outstanding as f64,
+        IndexWeighting::Price => 1.0,
+    }
+}
+
+fn index_value(exchange: &str, index: &MarketIndex, at: Timestamp) -> IndexValue {
+    IndexValue {
+        exchange: exchange.to_string(),
+        index: index.config.name.clone(),
+        headline: index.headline,
+        value: index.value,
+        previous_close: index.previous_close,
+        change_percent: ((index.value / index.previous_close - 1.0) * 10000.0).round() / 100.0,
+        at,
+    }
+}
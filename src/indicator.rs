@@ -0,0 +1,404 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::candle::{BarHistory, BarInterval, Candle, BAR_HISTORY_LENGTH};
+
+// -------------------- Indicators --------------------
+// Each is computed over bars oldest first and is None until there are enough of them
+
+// Simple moving average of the last closes
+pub fn sma(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() < period {
+        return None;
+    }
+
+    Some(closes[closes.len() - period..].iter().sum::<f64>() / period as f64)
+}
+
+// Exponential moving average, seeded with the SMA of the first period
+pub fn ema(closes: &[f64], period: usize) -> Option<f64> {
+    ema_series(closes, period).last().copied()
+}
+
+fn ema_series(closes: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 || closes.len() < period {
+        return Vec::new();
+    }
+
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut series = vec![closes[..period].iter().sum::<f64>() / period as f64];
+
+    for close in closes[period..].iter() {
+        let previous = series[series.len() - 1];
+        series.push(previous + alpha * (close - previous));
+    }
+
+    series
+}
+
+// Wilder's relative strength index, 0 to 100
+pub fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() <= period {
+        return None;
+    }
+
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+    let mut average_gain = changes[..period].iter().map(|change| change.max(0.0)).sum::<f64>() / period as f64;
+    let mut average_loss = changes[..period].iter().map(|change| (-change).max(0.0)).sum::<f64>() / period as f64;
+
+    for change in changes[period..].iter() {
+        average_gain = (average_gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        average_loss = (average_loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+    }
+
+    if average_loss == 0.0 {
+        return Some(100.0);
+    }
+
+    Some(100.0 - 100.0 / (1.0 + average_gain / average_loss))
+}
+
+// MACD line, signal line and histogram
+pub fn macd(closes: &[f64], fast: usize, slow: usize, signal: usize) -> Option<(f64, f64, f64)> {
+    let fast_series = ema_series(closes, fast);
+    let slow_series = ema_series(closes, slow);
+
+    if slow_series.is_empty() || fast_series.len() < slow_series.len() {
+        return None;
+    }
+
+    // Line the two averages up on the bars both cover
+    let offset = fast_series.len() - slow_series.len();
+
+    let macd_series: Vec<f64> = slow_series.iter().enumerate()
+        .map(|(position, slow)| fast_series[position + offset] - slow)
+        .collect();
+
+    let macd_line = *macd_series.last()?;
+    let signal_line = ema(&macd_series, signal)?;
+
+    Some((macd_line, signal_line, macd_line - signal_line))
+}
+
+// Lower band, middle band (the SMA) and upper band, deviations standard deviations apart
+pub fn bollinger_bands(closes: &[f64], period: usize, deviations: f64) -> Option<(f64, f64, f64)> {
+    let middle = sma(closes, period)?;
+
+    let variance = closes[closes.len() - period..].iter()
+        .map(|close| (close - middle).powi(2))
+        .sum::<f64>() / period as f64;
+
+    let width = deviations * variance.sqrt();
+
+    Some((middle - width, middle, middle + width))
+}
+
+// Wilder's average true range
+pub fn atr(candles: &[&Candle], period: usize) -> Option<f64> {
+    if period == 0 || candles.len() <= period {
+        return None;
+    }
+
+    let true_ranges: Vec<f64> = candles.windows(2)
+        .map(|pair| {
+            let previous_close = pair[0].close.to_f64();
+            let (high, low) = (pair[1].high.to_f64(), pair[1].low.to_f64());
+
+            (high - low).max((high - previous_close).abs()).max((low - previous_close).abs())
+        })
+        .collect();
+
+    let mut average = true_ranges[..period].iter().sum::<f64>() / period as f64;
+
+    for true_range in true_ranges[period..].iter() {
+        average = (average * (period - 1) as f64 + true_range) / period as f64;
+    }
+
+    Some(average)
+}
+
+// Volume-weighted average of the typical price over the latest trading day, None before the first trade
+pub fn vwap(candles: &[&Candle]) -> Option<f64> {
+    let trading_day = candles.last()?.start.trading_day();
+
+    let (value, volume) = candles.iter()
+        .filter(|candle| candle.start.trading_day() == trading_day)
+        .fold((0.0, 0u64), |(value, volume), candle| {
+            let typical_price = (candle.high.to_f64() + candle.low.to_f64() + candle.close.to_f64()) / 3.0;
+            (value + typical_price * candle.volume as f64, volume + candle.volume)
+        });
+
+    if volume == 0 {
+        return None;
+    }
+
+    Some(value / volume as f64)
+}
+
+// -------------------- Conditions --------------------
+
+// One side of a condition: the last price, a fixed number, or an indicator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operand {
+    Price,
+    Value(f64),
+    Sma { period: usize },
+    Ema { period: usize },
+    Rsi { period: usize },
+    // The MACD histogram, above 0 while the MACD line is above its signal line
+    Macd { fast: usize, slow: usize, signal: usize },
+    BollingerUpper { period: usize, deviations: f64 },
+    BollingerLower { period: usize, deviations: f64 },
+    Atr { period: usize },
+    Vwap,
+}
+
+impl Operand {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Operand::Value(value) if !value.is_finite() => Err("indicator values must be numbers".to_string()),
+            Operand::Sma { period } | Operand::Ema { period } | Operand::Rsi { period } | Operand::Atr { period } if *period == 0 => {
+                Err(format!("{} needs a period of at least 1", self))
+            }
+            Operand::Macd { fast, slow, signal } if *fast == 0 || *signal == 0 || fast >= slow => {
+                Err(format!("{} needs 0 < fast < slow and a signal period of at least 1", self))
+            }
+            Operand::BollingerUpper { period, deviations } | Operand::BollingerLower { period, deviations } if *period == 0 || !deviations.is_finite() || *deviations <= 0.0 => {
+                Err(format!("{} needs a period of at least 1 and a positive width", self))
+            }
+            _ if self.bars_needed() > BAR_HISTORY_LENGTH => {
+                Err(format!("{} needs more than the {} bars the broker keeps", self, BAR_HISTORY_LENGTH))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fewest bars the value can be computed from
+    fn bars_needed(&self) -> usize {
+        match self {
+            Operand::Price | Operand::Value(_) | Operand::Vwap => 1,
+            Operand::Sma { period } | Operand::Ema { period } => *period,
+            Operand::BollingerUpper { period, .. } | Operand::BollingerLower { period, .. } => *period,
+            // One more close for the first change
+            Operand::Rsi { period } | Operand::Atr { period } => period.saturating_add(1),
+            Operand::Macd { slow, signal, .. } => slow.saturating_add(*signal) - 1,
+        }
+    }
+
+    // Value at the last of the bars
+    pub fn value(&self, candles: &[&Candle]) -> Option<f64> {
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close.to_f64()).collect();
+
+        match self {
+            Operand::Price => closes.last().copied(),
+            Operand::Value(value) => Some(*value),
+            Operand::Sma { period } => sma(&closes, *period),
+            Operand::Ema { period } => ema(&closes, *period),
+            Operand::Rsi { period } => rsi(&closes, *period),
+            Operand::Macd { fast, slow, signal } => macd(&closes, *fast, *slow, *signal).map(|(_, _, histogram)| histogram),
+            Operand::BollingerUpper { period, deviations } => bollinger_bands(&closes, *period, *deviations).map(|(_, _, upper)| upper),
+            Operand::BollingerLower { period, deviations } => bollinger_bands(&closes, *period, *deviations).map(|(lower, _, _)| lower),
+            Operand::Atr { period } => atr(candles, *period),
+            Operand::Vwap => vwap(candles),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Price => write!(f, "price"),
+            Operand::Value(value) => write!(f, "{}", value),
+            Operand::Sma { period } => write!(f, "SMA({})", period),
+            Operand::Ema { period } => write!(f, "EMA({})", period),
+            Operand::Rsi { period } => write!(f, "RSI({})", period),
+            Operand::Macd { fast, slow, signal } => write!(f, "MACD({}, {}, {})", fast, slow, signal),
+            Operand::BollingerUpper { period, deviations } => write!(f, "upper Bollinger({}, {})", period, deviations),
+            Operand::BollingerLower { period, deviations } => write!(f, "lower Bollinger({}, {})", period, deviations),
+            Operand::Atr { period } => write!(f, "ATR({})", period),
+            Operand::Vwap => write!(f, "VWAP"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Above,
+    Below,
+    // Below or level on the previous bar, above on the last
+    CrossesAbove,
+    CrossesBelow,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Comparison::Above => "above",
+            Comparison::Below => "below",
+            Comparison::CrossesAbove => "crosses above",
+            Comparison::CrossesBelow => "crosses below",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// e.g. RSI(14) below 30, or price crosses above SMA(20), on one bar interval of the broker's history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndicatorCondition {
+    pub left: Operand,
+    pub comparison: Comparison,
+    pub right: Operand,
+    #[serde(default = "condition_interval")]
+    pub interval: BarInterval,
+}
+
+fn condition_interval() -> BarInterval {
+    BarInterval::OneMinute
+}
+
+impl IndicatorCondition {
+    pub fn new(left: Operand, comparison: Comparison, right: Operand) -> IndicatorCondition {
+        IndicatorCondition { left, comparison, right, interval: condition_interval() }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.left.validate()?;
+        self.right.validate()?;
+
+        // A crossing compares against the bar before too
+        let crossing = matches!(self.comparison, Comparison::CrossesAbove | Comparison::CrossesBelow);
+
+        if crossing && self.left.bars_needed().max(self.right.bars_needed()) >= BAR_HISTORY_LENGTH {
+            return Err(format!("{} needs more than the {} bars the broker keeps", self, BAR_HISTORY_LENGTH));
+        }
+
+        Ok(())
+    }
+
+    // Never met while either side cannot be computed yet
+    pub fn is_met(&self, bar_history: &BarHistory, stock_symbol: &str) -> bool {
+        let candles = bar_history.bars(stock_symbol, self.interval);
+
+        let sides = |candles: &[&Candle]| -> Option<(f64, f64)> {
+            Some((self.left.value(candles)?, self.right.value(candles)?))
+        };
+
+        let (left, right) = match sides(&candles) {
+            Some(sides) => sides,
+            None => return false,
+        };
+
+        match self.comparison {
+            Comparison::Above => left > right,
+            Comparison::Below => left < right,
+            Comparison::CrossesAbove | Comparison::CrossesBelow => {
+                let previous = match candles.split_last().and_then(|(_, earlier)| sides(earlier)) {
+                    Some(previous) => previous,
+                    None => return false,
+                };
+
+                if self.comparison == Comparison::CrossesAbove {
+                    previous.0 <= previous.1 && left > right
+                } else {
+                    previous.0 >= previous.1 && left < right
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for IndicatorCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} on {} bars", self.left, self.comparison, self.right, self.interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::money::Money;
+    use crate::simulation::Timestamp;
+    use crate::stock_exchange::TRADING_DAY_SECONDS;
+
+    fn close_enough(value: Option<f64>, expected: f64) -> bool {
+        value.is_some_and(|value| (value - expected).abs() < 1e-9)
+    }
+
+    fn candle(start_secs: u64, high: i64, low: i64, close: i64, volume: u64) -> Candle {
+        Candle {
+            stock_symbol: "MYEG".to_string(),
+            interval: BarInterval::OneMinute,
+            start: Timestamp::from_duration(Duration::from_secs(start_secs)),
+            open: Money::from_ringgit(close),
+            high: Money::from_ringgit(high),
+            low: Money::from_ringgit(low),
+            close: Money::from_ringgit(close),
+            volume,
+            trades: 1,
+        }
+    }
+
+    #[test]
+    fn averages_the_latest_closes() {
+        let closes = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+        assert!(close_enough(sma(&closes, 3), 4.0));
+        assert!(close_enough(sma(&closes, 5), 3.0));
+        assert_eq!(sma(&closes, 6), None);
+        assert_eq!(sma(&closes, 0), None);
+    }
+
+    #[test]
+    fn seeds_the_exponential_average_with_the_simple_one() {
+        // Seeded at 20, then half way to 0
+        assert!(close_enough(ema(&[10.0, 20.0, 30.0, 0.0], 3), 10.0));
+        // Seeded at 3, then two thirds of the way to each close
+        assert!(close_enough(ema(&[2.0, 4.0, 6.0, 8.0, 12.0], 2), 31.0 / 3.0));
+        assert!(close_enough(ema(&[1.0, 2.0, 3.0], 3), 2.0));
+        assert_eq!(ema(&[1.0, 2.0], 3), None);
+    }
+
+    #[test]
+    fn smooths_gains_and_losses_into_a_relative_strength() {
+        // Gains average 0.5 then 0.75, losses 0.5 then 0.25
+        assert!(close_enough(rsi(&[1.0, 2.0, 1.0, 2.0], 2), 75.0));
+        assert!(close_enough(rsi(&[1.0, 2.0, 3.0], 2), 100.0));
+        assert!(close_enough(rsi(&[3.0, 2.0, 1.0], 2), 0.0));
+        // A period of changes needs one more close
+        assert_eq!(rsi(&[1.0, 2.0], 2), None);
+    }
+
+    #[test]
+    fn weights_the_typical_price_by_volume_over_the_latest_day() {
+        let yesterday = candle(0, 100, 100, 100, 1_000);
+        let first = candle(TRADING_DAY_SECONDS, 12, 9, 9, 100);
+        let second = candle(TRADING_DAY_SECONDS + 60, 21, 18, 21, 300);
+        let quiet = candle(TRADING_DAY_SECONDS + 120, 30, 30, 30, 0);
+
+        // Typical prices 10 and 20, the day before and the bar without trades left out
+        assert!(close_enough(vwap(&[&yesterday, &first, &second, &quiet]), 17.5));
+        assert_eq!(vwap(&[&quiet]), None);
+        assert_eq!(vwap(&[]), None);
+    }
+
+    #[test]
+    fn refuses_lookbacks_longer_than_the_bar_history() {
+        assert!(Operand::Sma { period: BAR_HISTORY_LENGTH }.validate().is_ok());
+        assert!(Operand::Sma { period: BAR_HISTORY_LENGTH + 1 }.validate().is_err());
+        assert!(Operand::Rsi { period: BAR_HISTORY_LENGTH }.validate().is_err());
+        assert!(Operand::Macd { fast: 12, slow: BAR_HISTORY_LENGTH, signal: 2 }.validate().is_err());
+        assert!(Operand::Ema { period: usize::MAX }.validate().is_err());
+        assert!(Operand::Atr { period: usize::MAX }.validate().is_err());
+
+        // A crossing needs the bar before as well
+        let longest = Operand::Sma { period: BAR_HISTORY_LENGTH };
+
+        assert!(IndicatorCondition::new(Operand::Price, Comparison::Above, longest.clone()).validate().is_ok());
+        assert!(IndicatorCondition::new(Operand::Price, Comparison::CrossesAbove, longest).validate().is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::circuit_breaker::PriceLimits;
+use crate::price_model::{FactorLoadings, PriceModel};
+use crate::trading_rules::{self, bursa_tick_table, TickBand, BOARD_LOT};
+
+// Used when the config does not name an instrument master
+pub const DEFAULT_INSTRUMENTS_PATH: &str = "config/instruments.json";
+
+// Fanout the stock exchange publishes the instrument list on
+pub const INSTRUMENT_LIST_EXCHANGE: &str = "instrument_list";
+
+// One listed stock as described by the instrument master
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instrument {
+    pub name: String,
+    pub symbol: String,
+    pub sector: String,
+    pub initial_price: Money,
+    // Annualised, e.g. 0.3 moves the price about 30% a year or 1.9% a trading day
+    pub volatility: f32,
+    #[serde(default = "board_lot")]
+    pub lot_size: u64,
+    // Capitalisation-weighted indices weigh the stock by it
+    #[serde(default = "shares_outstanding")]
+    pub shares_outstanding: u64,
+    // Empty means the file's tick table applies
+    #[serde(default)]
+    pub tick_table: Vec<TickBand>,
+    // GBM with no drift when not given
+    #[serde(default)]
+    pub price_model: PriceModel,
+    // The stock exchange's loadings when not given
+    #[serde(default)]
+    pub factor_loadings: Option<FactorLoadings>,
+    // 30% static and 10% dynamic limits when not given
+    #[serde(default)]
+    pub price_limits: PriceLimits,
+}
+
+impl Instrument {
+    pub fn tick_size(&self, price: Money) -> Money {
+        trading_rules::tick_size(&self.tick_table, price)
+    }
+
+    pub fn round_to_tick(&self, price: Money) -> Money {
+        trading_rules::round_to_tick(&self.tick_table, price)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InstrumentFile {
+    #[serde(default = "bursa_tick_table")]
+    tick_table: Vec<TickBand>,
+    instruments: Vec<Instrument>,
+}
+
+fn board_lot() -> u64 {
+    BOARD_LOT
+}
+
+fn shares_outstanding() -> u64 {
+    1_000_000_000
+}
+
+// Read the instrument master, every instrument comes back with its own tick table filled in
+pub fn load_instruments(path: &str) -> Result<Vec<Instrument>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+
+    let file: InstrumentFile = serde_json::from_str(&contents).map_err(|err| format!("Cannot parse {}: {}", path, err))?;
+
+    validate_tick_table(&file.tick_table).map_err(|err| format!("{}: {}", path, err))?;
+
+    let mut symbols = HashSet::new();
+    let mut instruments = file.instruments;
+
+    for instrument in instruments.iter_mut() {
+        if !symbols.insert(instrument.symbol.clone()) {
+            return Err(format!("{}: {} is listed more than once", path, instrument.symbol));
+        }
+
+        if instrument.lot_size == 0 {
+            return Err(format!("{}: {} has a lot size of 0", path, instrument.symbol));
+        }
+
+        if instrument.shares_outstanding == 0 {
+            return Err(format!("{}: {} has no shares outstanding", path, instrument.symbol));
+        }
+
+        if instrument.initial_price <= Money::ZERO {
+            return Err(format!("{}: {} needs a positive initial price", path, instrument.symbol));
+        }
+
+        if instrument.tick_table.is_empty() {
+            instrument.tick_table = file.tick_table.clone();
+        }
+
+        validate_tick_table(&instrument.tick_table).map_err(|err| format!("{}: {}: {}", path, instrument.symbol, err))?;
+
+        if !instrument.volatility.is_finite() || instrument.volatility < 0.0 {
+            return Err(format!("{}: {} needs a volatility of 0 or more", path, instrument.symbol));
+        }
+
+        instrument.price_model.validate().map_err(|err| format!("{}: {}: {}", path, instrument.symbol, err))?;
+
+        if let Some(factor_loadings) = &instrument.factor_loadings {
+            factor_loadings.validate().map_err(|err| format!("{}: {}: {}", path, instrument.symbol, err))?;
+        }
+
+        instrument.price_limits.validate().map_err(|err| format!("{}: {}: {}", path, instrument.symbol, err))?;
+    }
+
+    if instruments.is_empty() {
+        return Err(format!("{}: no instruments listed", path));
+    }
+
+    Ok(instruments)
+}
+
+// Bands must start at zero, go up in price and have positive ticks
+fn validate_tick_table(tick_table: &[TickBand]) -> Result<(), String> {
+    match tick_table.first() {
+        Some(band) if band.from == Money::ZERO => {}
+        _ => return Err("tick table must start from 0".to_string()),
+    }
+
+    if tick_table.iter().any(|band| band.tick <= Money::ZERO) {
+        return Err("tick sizes must be positive".to_string());
+    }
+
+    if tick_table.windows(2).any(|bands| bands[0].from >= bands[1].from) {
+        return Err("tick table bands must be in rising price order".to_string());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use colored::Colorize;
+use crossbeam_channel::{bounded, never, select, Receiver, Sender};
+
+use crate::transport::Transport;
+use crate::simulation::Simulation;
+
+// Components stop in this order, so brokers can still cancel at the stock exchange while they drain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Client,
+    Broker,
+    Exchange,
+}
+
+const SHUTDOWN_ORDER: [Stage; 3] = [Stage::Client, Stage::Broker, Stage::Exchange];
+
+// Held by a component, closes when the component is asked to stop
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: Receiver<()>,
+}
+
+impl Shutdown {
+    // For crossbeam select! next to the component's own channels
+    pub fn receiver(&self) -> &Receiver<()> {
+        &self.receiver
+    }
+
+    // Block until asked to stop
+    pub fn wait(&self) {
+        let _ = self.receiver.recv();
+    }
+}
+
+// Lines each component adds to the final summary
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl Summary {
+    pub fn record(&self, line: String) {
+        self.lines.lock().unwrap().push(line);
+    }
+}
+
+struct Component {
+    name: String,
+    stage: Stage,
+    // Dropping the sender closes the component's Shutdown
+    stop: Option<Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+// Starts every component, waits for Ctrl-C or SIGTERM, then stops them stage by stage
+pub struct Lifecycle {
+    components: Vec<Component>,
+    summary: Summary,
+    simulation: Simulation,
+}
+
+// Ctrl-C can only be listened for once per process, so this is set up once and every run shares it
+pub fn listen_for_interrupts() -> Receiver<()> {
+    let (sender, receiver) = bounded(1);
+    let interrupted = AtomicBool::new(false);
+
+    // A second signal gives up on draining
+    let handler = move || {
+        if interrupted.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+
+        let _ = sender.try_send(());
+    };
+
+    match ctrlc::set_handler(handler) {
+        Ok(()) => receiver,
+        Err(err) => {
+            println!("{}", format!("ERROR: Cannot listen for Ctrl-C: {}", err).red().bold());
+            never()
+        }
+    }
+}
+
+impl Lifecycle {
+    pub fn new(simulation: Simulation) -> Lifecycle {
+        Lifecycle { components: Vec::new(), summary: Summary::default(), simulation }
+    }
+
+    pub fn spawn<F>(&mut self, stage: Stage, name: String, component: F)
+    where
+        F: FnOnce(Shutdown, Summary) + Send + 'static,
+    {
+        let (stop, receiver) = bounded(0);
+        let summary = self.summary.clone();
+
+        // Deterministic runs wait for every component to subscribe before anything is delivered
+        self.simulation.expect_start();
+
+        let handle = thread::spawn(move || component(Shutdown { receiver }, summary));
+
+        self.components.push(Component { name, stage, stop: Some(stop), handle });
+    }
+
+    // Block until the simulation is interrupted or its time is up, shut everything down and print the summary
+    pub fn run(mut self, transport: Arc<dyn Transport>, interrupts: Receiver<()>) {
+        let (end_sender, end_receiver) = bounded(1);
+
+        // A run with a set length stops itself once that much simulated time has passed
+        let simulation = self.simulation.clone();
+
+        let end_of_simulation = self.simulation.duration().map(|duration| {
+            self.simulation.every("end of simulation", duration, duration, move || {
+                // Stop the clock here, not whenever the main thread gets round to it
+                simulation.release();
+
+                let _ = end_sender.try_send(());
+            })
+        });
+
+        self.simulation.launch();
+
+        select! {
+            recv(interrupts) -> _ => {}
+            recv(end_receiver) -> _ => {}
+        }
+
+        println!("\n{}", format!("Simulation stopped at {}", self.simulation.now()).yellow().bold());
+
+        println!("\n{}\n", "Shutting down, press Ctrl-C again to wait no longer".yellow().bold());
+
+        // Stopping happens in real time, brokers draining must not wait for their turn
+        self.simulation.release();
+        drop(end_of_simulation);
+
+        for stage in SHUTDOWN_ORDER {
+            let (stopping, running): (Vec<Component>, Vec<Component>) = self.components.into_iter().partition(|component| component.stage == stage);
+            self.components = running;
+
+            let mut stopping = stopping;
+
+            for component in stopping.iter_mut() {
+                component.stop.take();
+            }
+
+            for component in stopping {
+                if component.handle.join().is_err() {
+                    println!("{}", format!("ERROR: {} panicked", component.name).red().bold());
+                }
+            }
+        }
+
+        if let Err(err) = transport.close() {
+            println!("{}", format!("ERROR: Failed to close transport: {}", err).red().bold());
+        }
+
+        println!("\n{}", "Simulation Summary".green().bold());
+
+        for line in self.summary.lines.lock().unwrap().iter() {
+            println!("{}", line);
+        }
+    }
+}
@@ -1,48 +1,125 @@
+mod account;
 mod broker;
+mod candle;
+mod circuit_breaker;
 mod client;
+mod config;
+mod index;
+mod indicator;
+mod instrument;
+mod lifecycle;
+mod money;
+mod order_book;
+mod price_model;
+mod protocol;
+mod risk;
+mod session;
+mod simulation;
 mod stock_exchange;
+mod trading_rules;
+mod transport;
+mod trend;
+mod trigger;
 
-use std::thread;
+use std::env;
+use std::sync::Arc;
+use colored::Colorize;
+use crossbeam_channel::Receiver;
 use client::client;
 use broker::broker;
+use config::{MarketConfig, DEFAULT_CONFIG_PATH};
+use instrument::{load_instruments, Instrument};
+use lifecycle::{listen_for_interrupts, Lifecycle, Stage};
+use simulation::Simulation;
 use stock_exchange::stock_exchange;
+use transport::{AmqpTransport, InProcessTransport, Transport, TransportConfig};
 
 fn main() {
 
-    // Bursa Malaysia
-    thread::spawn(move || {
-        if let Err(err) = stock_exchange() {
-            println!("Error occurred in Stock Exchange: {:?}", err);
-        }
-    });
+    // Market topology comes from the config file given on the command line
+    let config_path = env::args().nth(1).unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
 
-    // Broker 1
-    thread::spawn(move || {
-        if let Err(err) = broker("1".to_string(), 254, 47, 12) {
-            println!("Error occurred in Broker 1: {:?}", err);
+    let config = match MarketConfig::load(&config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("{}", format!("ERROR: {}", err).red().bold());
+            return;
         }
-    });
+    };
 
-    // Broker 2
-    thread::spawn(move || {
-        if let Err(err) = broker("2".to_string(), 47, 12, 254) {
-            println!("Error occurred in Broker 2: {:?}", err);
+    let instruments = match load_instruments(&config.exchange.instruments_path) {
+        Ok(instruments) => instruments,
+        Err(err) => {
+            println!("{}", format!("ERROR: {}", err).red().bold());
+            return;
         }
-    });
+    };
 
-    // Client 1
-    thread::spawn(move || {
-        if let Err(err) = client("1".to_string(), "1".to_string()) {
-            println!("Error occurred in Client 1: {:?}", err);
-        }
-    });
+    println!("Starting {} with {} brokers, {} clients and {} instruments from {}\n", config.exchange.name, config.brokers.len(), config.clients.len(), instruments.len(), config_path);
+
+    // Print the seed so the run can be repeated
+    let simulation = Simulation::new(&config.simulation);
+
+    println!("Simulation seed {}, clock running {}{}\n", simulation.seed(), config.simulation.clock, if config.simulation.deterministic { ", deterministic scheduling" } else { "" });
 
-    // Client 2
-    thread::spawn(move || {
-        if let Err(err) = client("2".to_string(), "2".to_string()) {
-            println!("Error occurred in Client 2: {:?}", err);
+    // Everyone shares one transport: one RabbitMQ connection, or in-process channels
+    let transport: Arc<dyn Transport> = match &config.transport {
+        TransportConfig::Amqp { url } => match AmqpTransport::open(url) {
+            Ok(transport) => Arc::new(transport),
+            Err(err) => {
+                println!("{}", format!("ERROR: Cannot connect to {}: {}", url, err).red().bold());
+                return;
+            }
+        },
+        TransportConfig::InProcess => Arc::new(InProcessTransport::new(simulation.scheduler())),
+    };
+
+    run(config, instruments, simulation, transport, listen_for_interrupts());
+}
+
+// Start the stock exchange, brokers and clients on one transport, returns once the simulation has stopped
+fn run(config: MarketConfig, instruments: Vec<Instrument>, simulation: Simulation, transport: Arc<dyn Transport>, interrupts: Receiver<()>) {
+    // Every component runs under the lifecycle controller, which stops them on Ctrl-C
+    let mut lifecycle = Lifecycle::new(simulation.clone());
+
+    // Stock Exchange
+    let exchange_config = config.exchange.clone();
+    let exchange_transport = transport.clone();
+    let exchange_simulation = simulation.clone();
+
+    lifecycle.spawn(Stage::Exchange, exchange_config.name.clone(), move |shutdown, summary| {
+        let name = exchange_config.name.clone();
+
+        if let Err(err) = stock_exchange(exchange_transport, exchange_config, instruments, exchange_simulation, shutdown, summary) {
+            println!("Error occurred in {}: {:?}", name, err);
         }
     });
 
-    loop {}
+    // Brokers
+    for broker_config in config.brokers {
+        let transport = transport.clone();
+        let simulation = simulation.clone();
+
+        lifecycle.spawn(Stage::Broker, format!("Broker {}", broker_config.name), move |shutdown, summary| {
+            let [red, green, blue] = broker_config.colour;
+
+            if let Err(err) = broker(transport, broker_config.name.clone(), red, green, blue, broker_config.risk, simulation, shutdown, summary) {
+                println!("Error occurred in Broker {}: {:?}", broker_config.name, err);
+            }
+        });
+    }
+
+    // Clients
+    for client_config in config.clients {
+        let transport = transport.clone();
+        let simulation = simulation.clone();
+
+        lifecycle.spawn(Stage::Client, format!("Client {}", client_config.name), move |shutdown, summary| {
+            if let Err(err) = client(transport, client_config.name.clone(), client_config.broker, client_config.strategy, simulation, shutdown, summary) {
+                println!("Error occurred in Client {}: {:?}", client_config.name, err);
+            }
+        });
+    }
+
+    lifecycle.run(transport, interrupts);
 }
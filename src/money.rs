@@ -0,0 +1,331 @@
+use std::fmt;
+use std::str::FromStr;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// Thousandths of a ringgit per ringgit
+// Sen precision is enough for every price above RM 1, the extra digit keeps Bursa's half-sen tick exact
+const SCALE: i64 = 1000;
+
+// Ringgit amount held as a whole number of 0.1 sen, so prices and P&L never drift
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_ringgit(ringgit: i64) -> Money {
+        Money(ringgit * SCALE)
+    }
+
+    pub const fn from_sen(sen: i64) -> Money {
+        Money(sen * (SCALE / 100))
+    }
+
+    pub const fn from_milli(milli: i64) -> Money {
+        Money(milli)
+    }
+
+    // Only for values coming out of floating point maths (price models, old float payloads)
+    pub fn from_f64(ringgit: f64) -> Money {
+        Money((ringgit * SCALE as f64).round() as i64)
+    }
+
+    // Ringgit as a float for the price models, never for bookkeeping
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    // Price x shares, None when the value is too large to hold
+    pub fn value_of(price: Money, quantity: u64) -> Option<Money> {
+        i64::try_from(price.0 as i128 * quantity as i128).ok().map(Money)
+    }
+
+    pub const fn milli(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_multiple_of(self, step: Money) -> bool {
+        step.0 != 0 && self.0 % step.0 == 0
+    }
+
+    // Nearest multiple of the step, halves round away from zero
+    pub fn round_to(self, step: Money) -> Money {
+        if step.0 == 0 {
+            return self;
+        }
+
+        let half = step.0 / 2;
+        let rounded = if self.0 >= 0 { (self.0 + half) / step.0 } else { (self.0 - half) / step.0 };
+
+        Money(rounded * step.0)
+    }
+
+    // Multiply by a ratio, e.g. scale(102, 100) for +2%, rounded to the nearest 0.1 sen
+    pub fn scale(self, numerator: i64, denominator: i64) -> Money {
+        let product = self.0 as i128 * numerator as i128;
+        let denominator = denominator as i128;
+        let half = denominator / 2;
+
+        let rounded = if product >= 0 { (product + half) / denominator } else { (product - half) / denominator };
+
+        Money(rounded as i64)
+    }
+}
+
+impl fmt::Display for Money {
+    // Two decimals like a price board, three only when a half-sen is involved
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let ringgit = self.0.abs() / SCALE;
+        let fraction = self.0.abs() % SCALE;
+
+        if fraction % 10 == 0 {
+            write!(f, "{}{}.{:02}", sign, ringgit, fraction / 10)
+        } else {
+            write!(f, "{}{}.{:03}", sign, ringgit, fraction)
+        }
+    }
+}
+
+impl FromStr for Money {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Money, String> {
+        let value = value.trim();
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (digits, ""),
+        };
+
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(format!("'{}' is not an amount", value));
+        }
+
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(format!("'{}' is not an amount", value));
+        }
+
+        if fraction.len() > 3 {
+            return Err(format!("'{}' has more than three decimal places", value));
+        }
+
+        let parse = |part: &str| -> Result<i64, String> {
+            if part.is_empty() {
+                return Ok(0);
+            }
+
+            part.parse::<i64>().map_err(|_| format!("'{}' is not an amount", value))
+        };
+
+        let fraction_milli = parse(&format!("{:0<3}", fraction))?;
+        let milli = parse(whole)?.checked_mul(SCALE)
+            .and_then(|milli| milli.checked_add(fraction_milli))
+            .ok_or_else(|| format!("'{}' is too large an amount", value))?;
+
+        Ok(Money(if negative { -milli } else { milli }))
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+// Price x shares, saturating where value_of would refuse
+impl Mul<u64> for Money {
+    type Output = Money;
+
+    fn mul(self, quantity: u64) -> Money {
+        Money::value_of(self, quantity).unwrap_or(if self.0 < 0 { Money(i64::MIN) } else { Money(i64::MAX) })
+    }
+}
+
+// Value / shares, rounded to the nearest 0.1 sen
+impl Div<u64> for Money {
+    type Output = Money;
+
+    fn div(self, quantity: u64) -> Money {
+        self.scale(1, quantity as i64)
+    }
+}
+
+impl std::iter::Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, |total, amount| total + amount)
+    }
+}
+
+// Written to JSON as a decimal string so no float ever touches the amount
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+// Also accepts plain JSON numbers from older messages
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        struct MoneyVisitor;
+
+        impl de::Visitor<'_> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a ringgit amount as a decimal string or number")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Money, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Money, E> {
+                value.checked_mul(SCALE)
+                    .map(Money)
+                    .ok_or_else(|| E::custom(format!("{} is too large an amount", value)))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Money, E> {
+                i64::try_from(value).ok()
+                    .and_then(|value| value.checked_mul(SCALE))
+                    .map(Money)
+                    .ok_or_else(|| E::custom(format!("{} is too large an amount", value)))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Money, E> {
+                // i64::MAX as f64 rounds up to 2^63, which no longer fits
+                if !value.is_finite() || (value * SCALE as f64).abs() >= i64::MAX as f64 {
+                    return Err(E::custom(format!("{} is not an amount that can be held", value)));
+                }
+
+                Ok(Money::from_f64(value))
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ringgit_and_sen() {
+        assert_eq!("12.34".parse::<Money>(), Ok(Money::from_sen(1234)));
+        assert_eq!("100".parse::<Money>(), Ok(Money::from_ringgit(100)));
+        assert_eq!(".5".parse::<Money>(), Ok(Money::from_sen(50)));
+        assert_eq!("0.005".parse::<Money>(), Ok(Money::from_milli(5)));
+        assert_eq!(" -7.1 ".parse::<Money>(), Ok(Money::from_milli(-7100)));
+    }
+
+    #[test]
+    fn refuses_what_is_not_an_amount() {
+        assert!("".parse::<Money>().is_err());
+        assert!(".".parse::<Money>().is_err());
+        assert!("1.2345".parse::<Money>().is_err());
+        assert!("RM 5".parse::<Money>().is_err());
+        assert!("1e3".parse::<Money>().is_err());
+        assert!("--1".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn refuses_amounts_too_large_to_hold() {
+        let largest = i64::MAX / SCALE;
+
+        assert_eq!(format!("{}", largest).parse::<Money>(), Ok(Money::from_ringgit(largest)));
+        assert!(format!("{}", largest + 1).parse::<Money>().is_err());
+        assert!(format!("{}.999", largest).parse::<Money>().is_err());
+        assert!("99999999999999999999".parse::<Money>().is_err());
+
+        // JSON numbers from older messages are held to the same range
+        assert_eq!(serde_json::from_str::<Money>(&largest.to_string()).unwrap(), Money::from_ringgit(largest));
+        assert!(serde_json::from_str::<Money>(&(largest + 1).to_string()).is_err());
+        assert!(serde_json::from_str::<Money>("9223372036854775807").is_err());
+        assert!(serde_json::from_str::<Money>("-9223372036854775808").is_err());
+        assert!(serde_json::from_str::<Money>("18446744073709551615").is_err());
+        assert!(serde_json::from_str::<Money>("1e300").is_err());
+        assert!(serde_json::from_str::<Money>("-1e300").is_err());
+        assert_eq!(serde_json::from_str::<Money>("79.88").unwrap(), Money::from_sen(7988));
+        assert!(Money::deserialize(de::value::F64Deserializer::<de::value::Error>::new(f64::NAN)).is_err());
+        assert!(Money::deserialize(de::value::F64Deserializer::<de::value::Error>::new(f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn refuses_order_values_too_large_to_hold() {
+        assert_eq!(Money::value_of(Money::from_sen(7988), 300), Some(Money::from_ringgit(23964)));
+        assert_eq!(Money::value_of(Money::from_sen(7988), 18446744073709551600), None);
+        assert_eq!(Money::value_of(Money::from_ringgit(1_000_000), u64::MAX / 2), None);
+
+        // The operator saturates instead of wrapping to a negative cost
+        assert_eq!(Money::from_sen(7988) * 18446744073709551600, Money::from_milli(i64::MAX));
+    }
+
+    #[test]
+    fn displays_two_decimals_unless_half_a_sen() {
+        assert_eq!(Money::from_sen(7988).to_string(), "79.88");
+        assert_eq!(Money::from_milli(1005).to_string(), "1.005");
+        assert_eq!(Money::from_milli(-50).to_string(), "-0.05");
+        assert_eq!(Money::from_sen(1234).to_string().parse::<Money>(), Ok(Money::from_sen(1234)));
+    }
+
+    #[test]
+    fn rounds_halves_away_from_zero() {
+        let tick = Money::from_sen(5);
+
+        assert_eq!(Money::from_milli(1024).round_to(tick), Money::from_milli(1000));
+        assert_eq!(Money::from_milli(1025).round_to(tick), Money::from_milli(1050));
+        assert_eq!(Money::from_milli(-1025).round_to(tick), Money::from_milli(-1050));
+        assert_eq!(Money::from_milli(1025).round_to(Money::ZERO), Money::from_milli(1025));
+
+        assert_eq!(Money::from_milli(1).scale(1, 2), Money::from_milli(1));
+        assert_eq!(Money::from_milli(-1).scale(1, 2), Money::from_milli(-1));
+        assert_eq!(Money::from_ringgit(10) / 3, Money::from_milli(3333));
+        assert_eq!(Money::from_f64(0.0015), Money::from_milli(2));
+    }
+
+    #[test]
+    fn scales_large_amounts_without_overflowing() {
+        let large = Money::from_milli(4_000_000_000_000_000_000);
+
+        // Neither product fits in an i64, the results do
+        assert_eq!(large.scale(3, 3), large);
+        assert_eq!(large.scale(200, 100), Money::from_milli(8_000_000_000_000_000_000));
+    }
+}
@@ -0,0 +1,795 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use crate::money::Money;
+use crate::protocol;
+use crate::simulation::Timestamp;
+
+// Which side of the book an order goes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Buy => write!(f, "Buy"),
+            Side::Sell => write!(f, "Sell"),
+        }
+    }
+}
+
+// How the order is priced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OrderType {
+    Market,
+    Limit { price: Money },
+    Stop { stop_price: Money },
+    StopLimit { stop_price: Money, limit_price: Money },
+}
+
+// e.g. Limit RM 79.88, Stop RM 80.00 Limit RM 81.60
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderType::Market => write!(f, "Market"),
+            OrderType::Limit { price } => write!(f, "Limit RM {}", price),
+            OrderType::Stop { stop_price } => write!(f, "Stop RM {}", stop_price),
+            OrderType::StopLimit { stop_price, limit_price } => write!(f, "Stop RM {} Limit RM {}", stop_price, limit_price),
+        }
+    }
+}
+
+// How long the order stays working
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    Day,
+    Gtc,
+    Ioc,
+    Fok,
+}
+
+// An order resting in (or submitted to) the book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub order_id: String,
+    pub broker_name: String,
+    pub stock_symbol: String,
+    pub buy_or_sell: Side,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub quantity: u64,
+    pub filled_quantity: u64,
+    pub sequence: u64,
+    // Simulated time the exchange received it
+    pub entered_at: Timestamp,
+}
+
+impl Order {
+    // Worst price the order accepts, None for market orders
+    pub fn limit_price(&self) -> Option<Money> {
+        match self.order_type {
+            OrderType::Limit { price } => Some(price),
+            OrderType::StopLimit { limit_price, .. } => Some(limit_price),
+            OrderType::Market | OrderType::Stop { .. } => None,
+        }
+    }
+
+    fn is_buy(&self) -> bool {
+        self.buy_or_sell == Side::Buy
+    }
+
+    fn accepts(&self, price: Money) -> bool {
+        match self.limit_price() {
+            Some(limit) if self.is_buy() => price <= limit,
+            Some(limit) => price >= limit,
+            None => true,
+        }
+    }
+}
+
+// A match between a buyer and a seller
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    pub stock_symbol: String,
+    pub buy_order_id: String,
+    pub sell_order_id: String,
+    pub buy_broker: String,
+    pub sell_broker: String,
+    pub price: Money,
+    pub quantity: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Rejected,
+    Cancelled,
+    Replaced,
+    // Cancel or amend request refused, the order itself is unchanged
+    CancelRejected,
+}
+
+impl OrderStatus {
+    // No further reports follow a terminal status
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OrderStatus::Filled | OrderStatus::Rejected | OrderStatus::Cancelled)
+    }
+}
+
+// Sent to the owning broker every time an order changes state, an order can receive several
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    pub order_id: String,
+    pub broker_name: String,
+    pub stock_symbol: String,
+    // None when the request did not say, e.g. a cancel for an unknown order
+    #[serde(default)]
+    pub buy_or_sell: Option<Side>,
+    pub status: OrderStatus,
+    pub fill_quantity: u64,
+    pub fill_price: Money,
+    pub cum_quantity: u64,
+    pub leaves_quantity: u64,
+    pub reason: Option<String>,
+    // Simulated time of the state change, stamped by whoever sends the report
+    pub transact_time: Timestamp,
+}
+
+impl ExecutionReport {
+    fn new(order: &Order, status: OrderStatus) -> ExecutionReport {
+        ExecutionReport {
+            order_id: order.order_id.clone(),
+            broker_name: order.broker_name.clone(),
+            stock_symbol: order.stock_symbol.clone(),
+            buy_or_sell: Some(order.buy_or_sell),
+            status,
+            fill_quantity: 0,
+            fill_price: Money::ZERO,
+            cum_quantity: order.filled_quantity,
+            leaves_quantity: order.quantity,
+            reason: None,
+            transact_time: Timestamp::default(),
+        }
+    }
+
+    pub fn rejected(order: &Order, reason: &str) -> ExecutionReport {
+        let mut report = ExecutionReport::new(order, OrderStatus::Rejected);
+        report.leaves_quantity = 0;
+        report.reason = Some(reason.to_string());
+        report
+    }
+
+    // Reply to a cancel or amend that could not be applied
+    pub fn cancel_rejected(order_id: &str, broker_name: &str, stock_symbol: &str, reason: &str) -> ExecutionReport {
+        ExecutionReport {
+            order_id: order_id.to_string(),
+            broker_name: broker_name.to_string(),
+            stock_symbol: stock_symbol.to_string(),
+            buy_or_sell: None,
+            status: OrderStatus::CancelRejected,
+            fill_quantity: 0,
+            fill_price: Money::ZERO,
+            cum_quantity: 0,
+            leaves_quantity: 0,
+            reason: Some(reason.to_string()),
+            transact_time: Timestamp::default(),
+        }
+    }
+
+    // Reply to a request that could not be read, None when it does not even name its order
+    // A new order is rejected, anything else is a refused cancel or amend
+    pub fn unreadable(body: &[u8], reason: &str) -> Option<ExecutionReport> {
+        let (kind, request) = protocol::request_fields(body)?;
+
+        let field = |name: &str| request.get(name).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+
+        let order_id = request.get("order_id")?.as_str()?;
+
+        let mut report = ExecutionReport::cancel_rejected(order_id, &field("broker_name"), &field("stock_symbol"), reason);
+
+        if kind == "NewOrder" {
+            report.status = OrderStatus::Rejected;
+            report.buy_or_sell = request.get("buy_or_sell").and_then(|side| serde_json::from_value(side.clone()).ok());
+        }
+
+        Some(report)
+    }
+
+    pub fn cancelled(order: &Order, reason: &str) -> ExecutionReport {
+        let mut report = ExecutionReport::new(order, OrderStatus::Cancelled);
+        report.leaves_quantity = 0;
+        report.reason = Some(reason.to_string());
+        report
+    }
+
+    fn fill(order: &Order, quantity: u64, price: Money) -> ExecutionReport {
+        let status = if order.quantity == 0 { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+
+        let mut report = ExecutionReport::new(order, status);
+        report.fill_quantity = quantity;
+        report.fill_price = price;
+        report
+    }
+}
+
+// Everything that happened to the book as a result of one action
+#[derive(Debug, Clone, Default)]
+pub struct MatchResult {
+    pub reports: Vec<ExecutionReport>,
+    pub trades: Vec<Trade>,
+}
+
+impl MatchResult {
+    pub fn extend(&mut self, other: MatchResult) {
+        self.reports.extend(other.reports);
+        self.trades.extend(other.trades);
+    }
+
+    // Reports leave the exchange with the time they happened
+    pub fn stamp(&mut self, now: Timestamp) {
+        for report in self.reports.iter_mut() {
+            report.transact_time = now;
+        }
+    }
+}
+
+// Per-symbol limit order book
+// Bids are kept highest price first, asks lowest price first, ties broken by arrival sequence
+// Stop orders wait on the side until the last traded price reaches their stop price
+// In the call phase orders only queue up, an auction uncrosses them when it ends
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    pub stock_symbol: String,
+    pub bids: Vec<Order>,
+    pub asks: Vec<Order>,
+    pub stops: Vec<Order>,
+    next_sequence: u64,
+    call_phase: bool,
+}
+
+impl OrderBook {
+    pub fn new(stock_symbol: String) -> OrderBook {
+        OrderBook {
+            stock_symbol,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            stops: Vec::new(),
+            next_sequence: 0,
+            call_phase: false,
+        }
+    }
+
+    pub fn set_call_phase(&mut self, call_phase: bool) {
+        self.call_phase = call_phase;
+    }
+
+    pub fn is_call_phase(&self) -> bool {
+        self.call_phase
+    }
+
+    pub fn best_bid(&self) -> Option<Money> {
+        self.bids.first().and_then(|order| order.limit_price())
+    }
+
+    pub fn best_ask(&self) -> Option<Money> {
+        self.asks.first().and_then(|order| order.limit_price())
+    }
+
+    // Orders still waiting in the book, including untriggered stops
+    pub fn resting_orders(&self) -> usize {
+        self.bids.len() + self.asks.len() + self.stops.len()
+    }
+
+    pub fn submit(&mut self, mut order: Order) -> MatchResult {
+        order.sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let mut result = MatchResult::default();
+
+        if let Some(reason) = validate(&order) {
+            result.reports.push(ExecutionReport::rejected(&order, reason));
+            return result;
+        }
+
+        result.reports.push(ExecutionReport::new(&order, OrderStatus::New));
+
+        match order.order_type {
+            // Park stop orders until they are triggered
+            OrderType::Stop { .. } | OrderType::StopLimit { .. } => self.stops.push(order),
+            // Waits for the auction
+            OrderType::Limit { .. } if self.call_phase => self.rest(order),
+            OrderType::Market | OrderType::Limit { .. } => result.extend(self.execute(order)),
+        }
+
+        result
+    }
+
+    // Release every stop order whose stop price has been reached by the last traded price
+    pub fn trigger_stops(&mut self, last_price: Money) -> MatchResult {
+        // Nothing may trade before the auction
+        if self.call_phase {
+            return MatchResult::default();
+        }
+
+        let mut triggered = Vec::new();
+
+        let mut index = 0;
+        while index < self.stops.len() {
+            let reached = match self.stops[index].order_type {
+                OrderType::Stop { stop_price } | OrderType::StopLimit { stop_price, .. } => {
+                    if self.stops[index].is_buy() { last_price >= stop_price } else { last_price <= stop_price }
+                }
+                _ => false,
+            };
+
+            if reached {
+                triggered.push(self.stops.remove(index));
+            } else {
+                index += 1;
+            }
+        }
+
+        // Stop becomes a market order, stop-limit becomes a limit order
+        let mut result = MatchResult::default();
+
+        for mut order in triggered {
+            order.order_type = match order.order_type {
+                OrderType::StopLimit { limit_price, .. } => OrderType::Limit { price: limit_price },
+                _ => OrderType::Market,
+            };
+
+            result.extend(self.execute(order));
+        }
+
+        result
+    }
+
+    pub fn cancel(&mut self, order_id: &str, broker_name: &str) -> MatchResult {
+        let mut result = MatchResult::default();
+
+        match self.take(order_id, broker_name) {
+            Some(order) => result.reports.push(ExecutionReport::cancelled(&order, "Cancelled on request")),
+            None => result.reports.push(ExecutionReport::cancel_rejected(order_id, broker_name, &self.stock_symbol, "Order is not working")),
+        }
+
+        result
+    }
+
+    // Change the price and/or total quantity of a working order
+    // Only a quantity reduction at the same price keeps time priority
+    pub fn amend(&mut self, order_id: &str, broker_name: &str, order_type: OrderType, quantity: u64) -> MatchResult {
+        let mut result = MatchResult::default();
+
+        let original = match self.take(order_id, broker_name) {
+            Some(order) => order,
+            None => {
+                result.reports.push(ExecutionReport::cancel_rejected(order_id, broker_name, &self.stock_symbol, "Order is not working"));
+                return result;
+            }
+        };
+
+        let mut order = original.clone();
+        order.order_type = order_type;
+        order.quantity = quantity.saturating_sub(original.filled_quantity);
+
+        let same_kind = std::mem::discriminant(&order.order_type) == std::mem::discriminant(&original.order_type);
+        let reason = if !same_kind {
+            Some("Order type cannot be changed")
+        } else if order.quantity == 0 {
+            Some("New quantity must be above the filled quantity")
+        } else {
+            validate(&order)
+        };
+
+        if let Some(reason) = reason {
+            self.put_back(original);
+            result.reports.push(ExecutionReport::cancel_rejected(order_id, broker_name, &self.stock_symbol, reason));
+            return result;
+        }
+
+        let keeps_priority = order.order_type == original.order_type && order.quantity <= original.quantity;
+        if !keeps_priority {
+            order.sequence = self.next_sequence;
+            self.next_sequence += 1;
+        }
+
+        result.reports.push(ExecutionReport::new(&order, OrderStatus::Replaced));
+
+        // A new limit price may now cross the book
+        match order.order_type {
+            OrderType::Limit { .. } if !keeps_priority && !self.call_phase => result.extend(self.execute(order)),
+            _ => self.put_back(order),
+        }
+
+        result
+    }
+
+    // DAY orders do not survive the close
+    pub fn expire_day_orders(&mut self) -> MatchResult {
+        let mut result = MatchResult::default();
+
+        for side in [&mut self.bids, &mut self.asks, &mut self.stops] {
+            let (day, rest): (Vec<Order>, Vec<Order>) = side.drain(..).partition(|order| order.time_in_force == TimeInForce::Day);
+            *side = rest;
+
+            for order in day {
+                result.reports.push(ExecutionReport::cancelled(&order, "DAY order expired at market close"));
+            }
+        }
+
+        result
+    }
+
+    // Uncross the orders collected during the call phase at the single price that trades the most shares
+    // Ties go to the smallest imbalance between the sides, then to the price nearest the reference price
+    pub fn uncross(&mut self, reference_price: Money) -> MatchResult {
+        let mut result = MatchResult::default();
+
+        let price = match self.equilibrium_price(reference_price) {
+            Some(price) => price,
+            None => return result,
+        };
+
+        while let (Some(bid), Some(ask)) = (self.bids.first(), self.asks.first()) {
+            if bid.limit_price().unwrap_or_default() < price || ask.limit_price().unwrap_or_default() > price {
+                break;
+            }
+
+            let quantity = bid.quantity.min(ask.quantity);
+
+            for order in [&mut self.bids[0], &mut self.asks[0]] {
+                order.quantity -= quantity;
+                order.filled_quantity += quantity;
+                result.reports.push(ExecutionReport::fill(order, quantity, price));
+            }
+
+            result.trades.push(Trade {
+                stock_symbol: self.stock_symbol.clone(),
+                buy_order_id: self.bids[0].order_id.clone(),
+                sell_order_id: self.asks[0].order_id.clone(),
+                buy_broker: self.bids[0].broker_name.clone(),
+                sell_broker: self.asks[0].broker_name.clone(),
+                price,
+                quantity,
+            });
+
+            if self.bids[0].quantity == 0 {
+                self.bids.remove(0);
+            }
+
+            if self.asks[0].quantity == 0 {
+                self.asks.remove(0);
+            }
+        }
+
+        result
+    }
+
+    // Auction price, None when the book does not cross
+    fn equilibrium_price(&self, reference_price: Money) -> Option<Money> {
+        let mut candidates: Vec<Money> = self.bids.iter().chain(self.asks.iter())
+            .filter_map(|order| order.limit_price())
+            .collect();
+
+        candidates.sort();
+        candidates.dedup();
+
+        candidates.into_iter()
+            .map(|price| {
+                let demand: u64 = self.bids.iter().filter(|bid| bid.accepts(price)).map(|bid| bid.quantity).sum();
+                let supply: u64 = self.asks.iter().filter(|ask| ask.accepts(price)).map(|ask| ask.quantity).sum();
+                let distance = if price > reference_price { price - reference_price } else { reference_price - price };
+
+                (price, demand.min(supply), demand.abs_diff(supply), distance)
+            })
+            .filter(|(_, volume, _, _)| *volume > 0)
+            .min_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)).then(a.3.cmp(&b.3)))
+            .map(|(price, _, _, _)| price)
+    }
+
+    // Match a market or limit order against the opposite side, then rest or cancel the remainder
+    fn execute(&mut self, mut order: Order) -> MatchResult {
+        let is_buy = order.is_buy();
+        let mut result = MatchResult::default();
+
+        // Fill or kill needs the whole quantity available before touching the book
+        if order.time_in_force == TimeInForce::Fok && self.available_quantity(&order) < order.quantity {
+            result.reports.push(ExecutionReport::cancelled(&order, "FOK order could not be filled in full"));
+            return result;
+        }
+
+        {
+            let opposite = if is_buy { &mut self.asks } else { &mut self.bids };
+
+            while order.quantity > 0 && !opposite.is_empty() {
+                let resting = &mut opposite[0];
+                let resting_price = resting.limit_price().unwrap_or_default();
+
+                // Stop once the best opposite price no longer crosses
+                if !order.accepts(resting_price) {
+                    break;
+                }
+
+                // Trades always happen at the resting order's price
+                let quantity = order.quantity.min(resting.quantity);
+
+                order.quantity -= quantity;
+                order.filled_quantity += quantity;
+                resting.quantity -= quantity;
+                resting.filled_quantity += quantity;
+
+                let (buy_order, sell_order) = if is_buy { (&order, &*resting) } else { (&*resting, &order) };
+
+                result.trades.push(Trade {
+                    stock_symbol: self.stock_symbol.clone(),
+                    buy_order_id: buy_order.order_id.clone(),
+                    sell_order_id: sell_order.order_id.clone(),
+                    buy_broker: buy_order.broker_name.clone(),
+                    sell_broker: sell_order.broker_name.clone(),
+                    price: resting_price,
+                    quantity,
+                });
+
+                result.reports.push(ExecutionReport::fill(&order, quantity, resting_price));
+                result.reports.push(ExecutionReport::fill(resting, quantity, resting_price));
+
+                if resting.quantity == 0 {
+                    opposite.remove(0);
+                }
+            }
+        }
+
+        // Market and immediate orders never rest
+        let can_rest = order.order_type != OrderType::Market && matches!(order.time_in_force, TimeInForce::Day | TimeInForce::Gtc);
+
+        if order.quantity > 0 {
+            if can_rest {
+                self.rest(order);
+            } else {
+                result.reports.push(ExecutionReport::cancelled(&order, "Unfilled quantity of immediate order cancelled"));
+            }
+        }
+
+        result
+    }
+
+    // Quantity on the opposite side at prices the order accepts
+    fn available_quantity(&self, order: &Order) -> u64 {
+        let opposite = if order.is_buy() { &self.asks } else { &self.bids };
+
+        opposite.iter()
+            .take_while(|resting| order.accepts(resting.limit_price().unwrap_or_default()))
+            .map(|resting| resting.quantity)
+            .sum()
+    }
+
+    // Insert behind every order with a better price, or the same price and an earlier sequence (price-time priority)
+    fn rest(&mut self, order: Order) {
+        let price = order.limit_price().unwrap_or_default();
+        let behind = |resting: &Order| {
+            let resting_price = resting.limit_price().unwrap_or_default();
+            resting_price == price && resting.sequence > order.sequence
+        };
+
+        if order.is_buy() {
+            let position = self.bids.iter().position(|resting| resting.limit_price().unwrap_or_default() < price || behind(resting)).unwrap_or(self.bids.len());
+            self.bids.insert(position, order);
+        } else {
+            let position = self.asks.iter().position(|resting| resting.limit_price().unwrap_or_default() > price || behind(resting)).unwrap_or(self.asks.len());
+            self.asks.insert(position, order);
+        }
+    }
+
+    // Remove a working order owned by the broker from wherever it waits
+    fn take(&mut self, order_id: &str, broker_name: &str) -> Option<Order> {
+        for side in [&mut self.bids, &mut self.asks, &mut self.stops] {
+            if let Some(position) = side.iter().position(|order| order.order_id == order_id && order.broker_name == broker_name) {
+                return Some(side.remove(position));
+            }
+        }
+
+        None
+    }
+
+    fn put_back(&mut self, order: Order) {
+        match order.order_type {
+            OrderType::Stop { .. } | OrderType::StopLimit { .. } => self.stops.push(order),
+            _ => self.rest(order),
+        }
+    }
+}
+
+fn validate(order: &Order) -> Option<&'static str> {
+    if order.quantity == 0 {
+        return Some("Quantity must be positive");
+    }
+
+    match order.order_type {
+        OrderType::Limit { price } if price <= Money::ZERO => Some("Limit price must be positive"),
+        OrderType::Stop { stop_price } if stop_price <= Money::ZERO => Some("Stop price must be positive"),
+        OrderType::StopLimit { stop_price, limit_price } if stop_price <= Money::ZERO || limit_price <= Money::ZERO => Some("Stop and limit prices must be positive"),
+        // Stops wait for their trigger, so an immediate time in force makes no sense
+        OrderType::Stop { .. } | OrderType::StopLimit { .. } if matches!(order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok) => Some("Stop orders cannot be IOC or FOK"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(order_id: &str, buy_or_sell: Side, price: Money, quantity: u64) -> Order {
+        Order {
+            order_id: order_id.to_string(),
+            broker_name: "1".to_string(),
+            stock_symbol: "MYEG".to_string(),
+            buy_or_sell,
+            order_type: OrderType::Limit { price },
+            time_in_force: TimeInForce::Day,
+            quantity,
+            filled_quantity: 0,
+            sequence: 0,
+            entered_at: Timestamp::default(),
+        }
+    }
+
+    #[test]
+    fn fills_best_price_first_then_earliest_order() {
+        let mut order_book = OrderBook::new("MYEG".to_string());
+
+        order_book.submit(limit("early", Side::Sell, Money::from_sen(1000), 100));
+        order_book.submit(limit("late", Side::Sell, Money::from_sen(1000), 100));
+        order_book.submit(limit("cheapest", Side::Sell, Money::from_sen(990), 100));
+
+        let result = order_book.submit(limit("buy", Side::Buy, Money::from_sen(1000), 250));
+
+        let fills: Vec<(&str, Money, u64)> = result.trades.iter()
+            .map(|trade| (trade.sell_order_id.as_str(), trade.price, trade.quantity))
+            .collect();
+
+        assert_eq!(fills, vec![
+            ("cheapest", Money::from_sen(990), 100),
+            ("early", Money::from_sen(1000), 100),
+            ("late", Money::from_sen(1000), 50),
+        ]);
+
+        // What is left of the later order keeps its place
+        assert_eq!(order_book.asks.len(), 1);
+        assert_eq!(order_book.asks[0].order_id, "late");
+        assert_eq!(order_book.asks[0].quantity, 50);
+        assert!(order_book.bids.is_empty());
+    }
+
+    #[test]
+    fn rests_unfilled_quantity_of_a_partially_filled_order() {
+        let mut order_book = OrderBook::new("MYEG".to_string());
+
+        order_book.submit(limit("sell", Side::Sell, Money::from_sen(1000), 100));
+
+        let result = order_book.submit(limit("buy", Side::Buy, Money::from_sen(1010), 300));
+
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].price, Money::from_sen(1000));
+        assert_eq!(result.trades[0].quantity, 100);
+
+        let buy_fill = result.reports.iter().find(|report| report.order_id == "buy" && report.fill_quantity > 0).unwrap();
+        assert_eq!(buy_fill.status, OrderStatus::PartiallyFilled);
+        assert_eq!(buy_fill.cum_quantity, 100);
+        assert_eq!(buy_fill.leaves_quantity, 200);
+
+        let sell_fill = result.reports.iter().find(|report| report.order_id == "sell").unwrap();
+        assert_eq!(sell_fill.status, OrderStatus::Filled);
+        assert_eq!(sell_fill.leaves_quantity, 0);
+
+        assert!(order_book.asks.is_empty());
+        assert_eq!(order_book.best_bid(), Some(Money::from_sen(1010)));
+        assert_eq!(order_book.bids[0].quantity, 200);
+        assert_eq!(order_book.bids[0].filled_quantity, 100);
+    }
+
+    #[test]
+    fn gives_a_later_order_at_the_same_price_lower_priority() {
+        let mut order_book = OrderBook::new("MYEG".to_string());
+
+        order_book.submit(limit("first", Side::Buy, Money::from_sen(1000), 100));
+        order_book.submit(limit("better", Side::Buy, Money::from_sen(1005), 100));
+        order_book.submit(limit("second", Side::Buy, Money::from_sen(1000), 100));
+
+        let queue: Vec<&str> = order_book.bids.iter().map(|order| order.order_id.as_str()).collect();
+
+        assert_eq!(queue, vec!["better", "first", "second"]);
+    }
+
+    fn call_phase_book(orders: Vec<Order>) -> OrderBook {
+        let mut order_book = OrderBook::new("MYEG".to_string());
+        order_book.set_call_phase(true);
+
+        for order in orders {
+            order_book.submit(order);
+        }
+
+        order_book
+    }
+
+    #[test]
+    fn uncrosses_at_the_price_that_trades_the_most_shares() {
+        let mut order_book = call_phase_book(vec![
+            limit("buy high", Side::Buy, Money::from_sen(1020), 100),
+            limit("buy mid", Side::Buy, Money::from_sen(1010), 200),
+            limit("buy low", Side::Buy, Money::from_sen(1000), 100),
+            limit("sell low", Side::Sell, Money::from_sen(990), 100),
+            limit("sell mid", Side::Sell, Money::from_sen(1000), 100),
+            limit("sell high", Side::Sell, Money::from_sen(1010), 200),
+        ]);
+
+        // Nothing trades while the call phase collects orders
+        assert_eq!(order_book.resting_orders(), 6);
+
+        // 100, 200, 300 and 100 shares would trade at 9.90, 10.00, 10.10 and 10.20
+        let result = order_book.uncross(Money::from_sen(1000));
+
+        assert!(result.trades.iter().all(|trade| trade.price == Money::from_sen(1010)));
+        assert_eq!(result.trades.iter().map(|trade| trade.quantity).sum::<u64>(), 300);
+
+        assert_eq!(order_book.best_bid(), Some(Money::from_sen(1000)));
+        assert_eq!(order_book.best_ask(), Some(Money::from_sen(1010)));
+        assert_eq!(order_book.asks[0].quantity, 100);
+    }
+
+    #[test]
+    fn breaks_a_volume_tie_on_the_smaller_imbalance() {
+        // 100 shares trade at either price, with 50 left over at 10.10
+        let mut order_book = call_phase_book(vec![
+            limit("buy", Side::Buy, Money::from_sen(1010), 100),
+            limit("sell low", Side::Sell, Money::from_sen(990), 100),
+            limit("sell high", Side::Sell, Money::from_sen(1010), 50),
+        ]);
+
+        // Even though the reference price is at 10.10
+        assert_eq!(order_book.equilibrium_price(Money::from_sen(1010)), Some(Money::from_sen(990)));
+
+        let result = order_book.uncross(Money::from_sen(1010));
+
+        let fills: Vec<(&str, Money, u64)> = result.trades.iter()
+            .map(|trade| (trade.sell_order_id.as_str(), trade.price, trade.quantity))
+            .collect();
+
+        assert_eq!(fills, vec![("sell low", Money::from_sen(990), 100)]);
+        assert_eq!(order_book.asks[0].order_id, "sell high");
+    }
+
+    #[test]
+    fn breaks_a_volume_and_imbalance_tie_on_the_reference_price() {
+        let order_book = call_phase_book(vec![
+            limit("buy", Side::Buy, Money::from_sen(1010), 100),
+            limit("sell", Side::Sell, Money::from_sen(990), 100),
+        ]);
+
+        assert_eq!(order_book.equilibrium_price(Money::from_sen(1005)), Some(Money::from_sen(1010)));
+        assert_eq!(order_book.equilibrium_price(Money::from_sen(995)), Some(Money::from_sen(990)));
+        assert_eq!(order_book.equilibrium_price(Money::from_sen(2000)), Some(Money::from_sen(1010)));
+    }
+
+    #[test]
+    fn uncrosses_nothing_when_no_bid_reaches_an_ask() {
+        let mut order_book = call_phase_book(vec![
+            limit("buy", Side::Buy, Money::from_sen(990), 100),
+            limit("sell", Side::Sell, Money::from_sen(1000), 100),
+        ]);
+
+        assert_eq!(order_book.equilibrium_price(Money::from_sen(995)), None);
+
+        let result = order_book.uncross(Money::from_sen(995));
+
+        assert!(result.trades.is_empty());
+        assert!(result.reports.is_empty());
+        assert_eq!(order_book.resting_orders(), 2);
+    }
+}
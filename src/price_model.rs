@@ -0,0 +1,197 @@
+use std::f64::consts::PI;
+use std::collections::BTreeMap;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::instrument::Instrument;
+
+// Model rates are annualised over this many trading days
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+// How an instrument's price moves each tick
+// Drift, volatility, mean reversion and jump intensity are annual rates, e.g. a volatility of 0.3 is 30% a year
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum PriceModel {
+    // Geometric Brownian motion
+    Gbm {
+        #[serde(default)]
+        drift: f64,
+    },
+    // Ornstein-Uhlenbeck on the log price, pulled back towards the long run mean
+    OrnsteinUhlenbeck {
+        mean_reversion: f64,
+        // Initial price when not given
+        #[serde(default)]
+        long_run_mean: Option<Money>,
+    },
+    // Merton jump-diffusion: GBM plus jumps arriving jump_intensity times a year on average
+    JumpDiffusion {
+        #[serde(default)]
+        drift: f64,
+        jump_intensity: f64,
+        // Mean and volatility of the log size of a jump
+        jump_mean: f64,
+        jump_volatility: f64,
+    },
+}
+
+impl Default for PriceModel {
+    fn default() -> PriceModel {
+        PriceModel::Gbm { drift: 0.0 }
+    }
+}
+
+impl PriceModel {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            PriceModel::Gbm { drift } if !drift.is_finite() => Err("GBM drift must be a number".to_string()),
+            PriceModel::OrnsteinUhlenbeck { mean_reversion, long_run_mean } => {
+                if !mean_reversion.is_finite() || *mean_reversion <= 0.0 {
+                    return Err("mean reversion must be positive".to_string());
+                }
+
+                if long_run_mean.is_some_and(|mean| mean <= Money::ZERO) {
+                    return Err("long run mean must be positive".to_string());
+                }
+
+                Ok(())
+            }
+            PriceModel::JumpDiffusion { drift, jump_intensity, jump_mean, jump_volatility } => {
+                if !drift.is_finite() || !jump_mean.is_finite() {
+                    return Err("jump-diffusion drift and jump mean must be numbers".to_string());
+                }
+
+                if !jump_intensity.is_finite() || *jump_intensity < 0.0 || !jump_volatility.is_finite() || *jump_volatility < 0.0 {
+                    return Err("jump intensity and jump volatility cannot be negative".to_string());
+                }
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Price after dt trading days, on the instrument's tick grid and never below one tick
+    // The shock is the tick's standard normal draw, correlated with other stocks through the factor shocks
+    pub fn next_price<R: Rng>(&self, instrument: &Instrument, price: Money, volatility: f32, dt: f64, shock: f64, rng: &mut R) -> Money {
+        let sigma = volatility as f64;
+        let dt = dt / TRADING_DAYS_PER_YEAR;
+        let log_price = price.to_f64().ln();
+
+        let next_log_price = match self {
+            PriceModel::Gbm { drift } => {
+                log_price + (drift - sigma * sigma / 2.0) * dt + sigma * dt.sqrt() * shock
+            }
+            PriceModel::OrnsteinUhlenbeck { mean_reversion, long_run_mean } => {
+                let log_mean = long_run_mean.unwrap_or(instrument.initial_price).to_f64().ln();
+
+                // Exact step, so long ticks do not overshoot the mean
+                let decay = (-mean_reversion * dt).exp();
+                let spread = sigma * ((1.0 - decay * decay) / (2.0 * mean_reversion)).sqrt();
+
+                log_mean + (log_price - log_mean) * decay + spread * shock
+            }
+            PriceModel::JumpDiffusion { drift, jump_intensity, jump_mean, jump_volatility } => {
+                // Drift is compensated so jumps do not change the expected return
+                let mean_jump = (jump_mean + jump_volatility * jump_volatility / 2.0).exp() - 1.0;
+                let diffusion = (drift - sigma * sigma / 2.0 - jump_intensity * mean_jump) * dt + sigma * dt.sqrt() * shock;
+
+                // Jumps are the company's own news
+                let jumps: f64 = (0..poisson(jump_intensity * dt, rng))
+                    .map(|_| jump_mean + jump_volatility * standard_normal(rng))
+                    .sum();
+
+                log_price + diffusion + jumps
+            }
+        };
+
+        let next_price = instrument.round_to_tick(Money::from_f64(next_log_price.exp()));
+
+        next_price.max(instrument.tick_size(Money::ZERO))
+    }
+}
+
+// Share of a stock's price variance driven by the whole market and by its sector, the rest is its own
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FactorLoadings {
+    pub market: f64,
+    pub sector: f64,
+}
+
+impl Default for FactorLoadings {
+    // Stocks in one sector move with a correlation of 0.3, across sectors 0.1
+    fn default() -> FactorLoadings {
+        FactorLoadings {
+            market: 0.1,
+            sector: 0.2,
+        }
+    }
+}
+
+impl FactorLoadings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.market) || !(0.0..=1.0).contains(&self.sector) || self.market + self.sector > 1.0 {
+            return Err("market and sector loadings must be between 0 and 1 and add up to at most 1".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+// Market and sector shocks shared by every stock in one tick
+#[derive(Debug)]
+pub struct FactorShocks {
+    market: f64,
+    sectors: BTreeMap<String, f64>,
+}
+
+impl FactorShocks {
+    // Sectors are drawn in name order so a seeded run repeats its shocks
+    pub fn draw<'a, R: Rng>(sectors: impl Iterator<Item = &'a str>, rng: &mut R) -> FactorShocks {
+        let market = standard_normal(rng);
+
+        let mut sectors: Vec<&str> = sectors.collect();
+        sectors.sort();
+        sectors.dedup();
+
+        let sectors = sectors.into_iter()
+            .map(|sector| (sector.to_string(), standard_normal(rng)))
+            .collect();
+
+        FactorShocks { market, sectors }
+    }
+
+    // One stock's standard normal shock, its own part drawn fresh
+    pub fn shock<R: Rng>(&self, loadings: &FactorLoadings, sector: &str, rng: &mut R) -> f64 {
+        let sector_shock = self.sectors.get(sector).copied().unwrap_or_else(|| standard_normal(rng));
+        let own = standard_normal(rng);
+
+        loadings.market.sqrt() * self.market + loadings.sector.sqrt() * sector_shock + (1.0 - loadings.market - loadings.sector).sqrt() * own
+    }
+}
+
+// Box-Muller, rand on its own only gives uniform numbers
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    // 1 - u keeps the log away from zero
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+// Knuth's method, fine for the small means of one tick
+fn poisson<R: Rng>(mean: f64, rng: &mut R) -> u32 {
+    let limit = (-mean).exp();
+    let mut product: f64 = rng.gen();
+    let mut count = 0;
+
+    while product > limit {
+        product *= rng.gen::<f64>();
+        count += 1;
+    }
+
+    count
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::broker::ExchangeRequest;
+use crate::candle::Candle;
+use crate::circuit_breaker::HaltNotice;
+use crate::client::ClientRequest;
+use crate::index::IndexValue;
+use crate::instrument::Instrument;
+use crate::order_book::ExecutionReport;
+use crate::session::SessionChange;
+use crate::simulation::{Clock, Timestamp};
+use crate::stock_exchange::Stock;
+use crate::transport::{Message, Result, Transport};
+
+// Version of the envelope and payloads sent now, bare payloads from before the envelope count as version 0
+pub const SCHEMA_VERSION: u32 = 1;
+
+// What an envelope carries, so a queue with several kinds of payload can tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageType {
+    Stock,
+    SessionChange,
+    HaltNotice,
+    InstrumentList,
+    IndexValue,
+    Candle,
+    ClientRequest,
+    ExchangeRequest,
+    ExecutionReport,
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MessageType::Stock => "stock",
+            MessageType::SessionChange => "session_change",
+            MessageType::HaltNotice => "halt_notice",
+            MessageType::InstrumentList => "instrument_list",
+            MessageType::IndexValue => "index_value",
+            MessageType::Candle => "candle",
+            MessageType::ClientRequest => "client_request",
+            MessageType::ExchangeRequest => "exchange_request",
+            MessageType::ExecutionReport => "execution_report",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// Anything sent between the stock exchange, brokers and clients
+pub trait Payload: Serialize + DeserializeOwned {
+    const MESSAGE_TYPE: MessageType;
+}
+
+impl Payload for Stock { const MESSAGE_TYPE: MessageType = MessageType::Stock; }
+impl Payload for SessionChange { const MESSAGE_TYPE: MessageType = MessageType::SessionChange; }
+impl Payload for HaltNotice { const MESSAGE_TYPE: MessageType = MessageType::HaltNotice; }
+impl Payload for Vec<Instrument> { const MESSAGE_TYPE: MessageType = MessageType::InstrumentList; }
+impl Payload for IndexValue { const MESSAGE_TYPE: MessageType = MessageType::IndexValue; }
+impl Payload for Candle { const MESSAGE_TYPE: MessageType = MessageType::Candle; }
+impl Payload for ClientRequest { const MESSAGE_TYPE: MessageType = MessageType::ClientRequest; }
+impl Payload for ExchangeRequest { const MESSAGE_TYPE: MessageType = MessageType::ExchangeRequest; }
+impl Payload for ExecutionReport { const MESSAGE_TYPE: MessageType = MessageType::ExecutionReport; }
+
+// Wraps every payload on the wire
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub message_type: MessageType,
+    pub schema_version: u32,
+    // Sender and sequence number, e.g. broker_1-42, empty on bare payloads
+    pub message_id: String,
+    // Simulated time it was sent
+    pub timestamp: Timestamp,
+    pub sender: String,
+    pub payload: T,
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    // Not JSON, or not the payload it claims to be
+    Unreadable(serde_json::Error),
+    UnsupportedVersion(u32),
+    UnexpectedType { expected: MessageType, found: MessageType },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Unreadable(err) => write!(f, "{}", err),
+            ProtocolError::UnsupportedVersion(version) => write!(f, "schema version {} is newer than {}", version, SCHEMA_VERSION),
+            ProtocolError::UnexpectedType { expected, found } => write!(f, "expected {} but got {}", expected, found),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProtocolError {
+    fn from(err: serde_json::Error) -> ProtocolError {
+        ProtocolError::Unreadable(err)
+    }
+}
+
+// Read an enveloped payload, or a bare one from a sender that predates the envelope
+pub fn decode<T: Payload>(body: &[u8]) -> std::result::Result<Envelope<T>, ProtocolError> {
+    let value: serde_json::Value = serde_json::from_slice(body)?;
+
+    if !is_envelope(&value) {
+        return Ok(Envelope {
+            message_type: T::MESSAGE_TYPE,
+            schema_version: 0,
+            message_id: String::new(),
+            timestamp: Timestamp::default(),
+            sender: String::new(),
+            payload: serde_json::from_value(value)?,
+        });
+    }
+
+    let envelope: Envelope<serde_json::Value> = serde_json::from_value(value)?;
+
+    if envelope.schema_version > SCHEMA_VERSION {
+        return Err(ProtocolError::UnsupportedVersion(envelope.schema_version));
+    }
+
+    if envelope.message_type != T::MESSAGE_TYPE {
+        return Err(ProtocolError::UnexpectedType { expected: T::MESSAGE_TYPE, found: envelope.message_type });
+    }
+
+    Ok(Envelope {
+        message_type: envelope.message_type,
+        schema_version: envelope.schema_version,
+        message_id: envelope.message_id,
+        timestamp: envelope.timestamp,
+        sender: envelope.sender,
+        payload: serde_json::from_value(envelope.payload)?,
+    })
+}
+
+// Kind of request and its fields, for answering a request too broken to decode
+// Requests are tagged with their kind, e.g. {"NewOrder": {...}}, enveloped or not
+pub fn request_fields(body: &[u8]) -> Option<(String, serde_json::Map<String, serde_json::Value>)> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+
+    let request = if is_envelope(&value) { value.get("payload")? } else { &value };
+
+    let (kind, fields) = request.as_object()?.iter().next()?;
+
+    Some((kind.clone(), fields.as_object()?.clone()))
+}
+
+fn is_envelope(value: &serde_json::Value) -> bool {
+    value.get("schema_version").is_some() && value.get("payload").is_some()
+}
+
+// Sends one component's payloads in envelopes, numbering them in the order they go out
+#[derive(Clone)]
+pub struct Outbox {
+    transport: Arc<dyn Transport>,
+    sender: String,
+    clock: Clock,
+    sequence: Arc<AtomicU64>,
+}
+
+impl Outbox {
+    pub fn new(transport: Arc<dyn Transport>, sender: &str, clock: Clock) -> Outbox {
+        Outbox { transport, sender: sender.to_string(), clock, sequence: Arc::new(AtomicU64::new(0)) }
+    }
+
+    fn encode<T: Payload>(&self, payload: &T) -> Vec<u8> {
+        let envelope = Envelope {
+            message_type: T::MESSAGE_TYPE,
+            schema_version: SCHEMA_VERSION,
+            message_id: format!("{}-{}", self.sender, self.sequence.fetch_add(1, Ordering::Relaxed) + 1),
+            timestamp: self.clock.now(),
+            sender: self.sender.clone(),
+            payload,
+        };
+
+        serde_json::to_vec(&envelope).unwrap()
+    }
+
+    pub fn publish<T: Payload>(&self, queue: &str, payload: &T) -> Result<()> {
+        self.transport.publish(queue, Message::new(self.encode(payload)))
+    }
+
+    pub fn broadcast<T: Payload>(&self, exchange: &str, payload: &T) -> Result<()> {
+        self.transport.broadcast(exchange, &self.encode(payload))
+    }
+
+    pub fn request<T: Payload>(&self, queue: &str, payload: &T, reply_to: &str, correlation_id: &str) -> Result<()> {
+        self.transport.request(queue, &self.encode(payload), reply_to, correlation_id)
+    }
+
+    pub fn reply<T: Payload>(&self, reply_to: &str, correlation_id: &str, payload: &T) -> Result<()> {
+        self.transport.reply(reply_to, correlation_id, &self.encode(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::json;
+
+    use super::*;
+    use crate::session::TradingSession;
+
+    fn session_change() -> SessionChange {
+        SessionChange {
+            exchange: "Bursa Malaysia".to_string(),
+            session: TradingSession::MorningSession,
+            at: Timestamp::from_duration(Duration::from_secs(30 * 60)),
+        }
+    }
+
+    fn enveloped(message_type: MessageType, schema_version: u32) -> Vec<u8> {
+        let envelope = Envelope {
+            message_type,
+            schema_version,
+            message_id: "exchange-7".to_string(),
+            timestamp: Timestamp::from_duration(Duration::from_secs(60)),
+            sender: "exchange".to_string(),
+            payload: session_change(),
+        };
+
+        serde_json::to_vec(&envelope).unwrap()
+    }
+
+    #[test]
+    fn reads_an_envelope_it_can_handle() {
+        let envelope = decode::<SessionChange>(&enveloped(MessageType::SessionChange, SCHEMA_VERSION)).unwrap();
+
+        assert_eq!(envelope.message_type, MessageType::SessionChange);
+        assert_eq!(envelope.schema_version, SCHEMA_VERSION);
+        assert_eq!(envelope.message_id, "exchange-7");
+        assert_eq!(envelope.sender, "exchange");
+        assert_eq!(envelope.timestamp, Timestamp::from_duration(Duration::from_secs(60)));
+        assert_eq!(envelope.payload.session, TradingSession::MorningSession);
+    }
+
+    #[test]
+    fn reads_a_bare_payload_as_schema_version_0() {
+        let body = serde_json::to_vec(&session_change()).unwrap();
+
+        let envelope = decode::<SessionChange>(&body).unwrap();
+
+        assert_eq!(envelope.message_type, MessageType::SessionChange);
+        assert_eq!(envelope.schema_version, 0);
+        assert_eq!(envelope.message_id, "");
+        assert_eq!(envelope.sender, "");
+        assert_eq!(envelope.timestamp, Timestamp::default());
+        assert_eq!(envelope.payload.exchange, "Bursa Malaysia");
+        assert_eq!(envelope.payload.at, Timestamp::from_duration(Duration::from_secs(30 * 60)));
+    }
+
+    #[test]
+    fn refuses_an_envelope_of_another_message_type() {
+        match decode::<SessionChange>(&enveloped(MessageType::HaltNotice, SCHEMA_VERSION)) {
+            Err(ProtocolError::UnexpectedType { expected, found }) => {
+                assert_eq!(expected, MessageType::SessionChange);
+                assert_eq!(found, MessageType::HaltNotice);
+            }
+            other => panic!("expected an unexpected type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn refuses_a_newer_schema_version_and_what_is_not_a_payload() {
+        assert!(matches!(
+            decode::<SessionChange>(&enveloped(MessageType::SessionChange, SCHEMA_VERSION + 1)),
+            Err(ProtocolError::UnsupportedVersion(version)) if version == SCHEMA_VERSION + 1
+        ));
+
+        assert!(matches!(decode::<SessionChange>(b"not json"), Err(ProtocolError::Unreadable(_))));
+
+        let wrong_payload = serde_json::to_vec(&json!({ "schema_version": 1, "message_type": "session_change", "message_id": "", "timestamp": 0, "sender": "", "payload": { "exchange": 7 } })).unwrap();
+        assert!(matches!(decode::<SessionChange>(&wrong_payload), Err(ProtocolError::Unreadable(_))));
+    }
+}
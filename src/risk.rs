@@ -0,0 +1,273 @@
+use std::fmt;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::account::ClientAccount;
+use crate::order_book::{OrderType, Side};
+use crate::client::ClientStockPreference;
+use crate::simulation::{Clock, Timestamp};
+
+// Limits a broker enforces before an order reaches the stock exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskLimits {
+    pub max_order_value: Money,
+    pub max_position: u64,
+    pub daily_loss_limit: Money,
+    // How far (percent) an order's price may stray from the last price
+    pub price_band_percent: i64,
+    pub max_orders_per_minute: usize,
+}
+
+impl Default for RiskLimits {
+    fn default() -> RiskLimits {
+        RiskLimits {
+            max_order_value: Money::from_ringgit(500_000),
+            max_position: 5_000,
+            daily_loss_limit: Money::from_ringgit(100_000),
+            price_band_percent: 30,
+            max_orders_per_minute: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskRule {
+    MaxOrderValue,
+    MaxPosition,
+    DailyLossLimit,
+    PriceBand,
+    OrderRate,
+}
+
+impl fmt::Display for RiskRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RiskRule::MaxOrderValue => "max order value",
+            RiskRule::MaxPosition => "max position",
+            RiskRule::DailyLossLimit => "daily loss limit",
+            RiskRule::PriceBand => "price band",
+            RiskRule::OrderRate => "order rate",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// The rule an order tripped and why
+#[derive(Debug, Clone)]
+pub struct RiskRejection {
+    pub rule: RiskRule,
+    pub reason: String,
+}
+
+impl fmt::Display for RiskRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Risk check failed ({}): {}", self.rule, self.reason)
+    }
+}
+
+// Equity a client opened the exchange's trading day with
+#[derive(Debug, Clone)]
+struct TradingDay {
+    day: u64,
+    opening_equity: Money,
+}
+
+pub struct RiskManager {
+    pub limits: RiskLimits,
+    recent_orders: HashMap<String, VecDeque<Timestamp>>,
+    trading_days: HashMap<String, TradingDay>,
+    clock: Clock,
+}
+
+impl RiskManager {
+    pub fn new(limits: RiskLimits, clock: Clock) -> RiskManager {
+        RiskManager {
+            limits,
+            recent_orders: HashMap::new(),
+            trading_days: HashMap::new(),
+            clock,
+        }
+    }
+
+    // Count a new or replaced order against the client's rate limit
+    pub fn check_rate(&mut self, client_number: &str) -> Result<(), RiskRejection> {
+        let now = self.clock.now();
+        let window = Duration::from_secs(60);
+
+        let recent_orders = self.recent_orders.entry(client_number.to_string()).or_default();
+
+        while recent_orders.front().is_some_and(|sent| now.since(*sent) >= window) {
+            recent_orders.pop_front();
+        }
+
+        if recent_orders.len() >= self.limits.max_orders_per_minute {
+            return Err(RiskRejection {
+                rule: RiskRule::OrderRate,
+                reason: format!("Client {} already sent {} orders in the last minute", client_number, recent_orders.len()),
+            });
+        }
+
+        recent_orders.push_back(now);
+
+        Ok(())
+    }
+
+    // Check an order against the client's account just before it is sent
+    // Opening prices are the stock exchange's reference prices for the current trading day
+    // A replace passes its new total quantity and what is already filled on the order
+    pub fn check_order(&mut self, client_preference: &ClientStockPreference, filled: u64, account: &ClientAccount, last_prices: &HashMap<String, Money>, opening_prices: &HashMap<String, Money>) -> Result<(), RiskRejection> {
+        let last_price = last_prices.get(&client_preference.stock_symbol).copied();
+        let is_buy = client_preference.buy_or_sell == Side::Buy;
+
+        // Fat-finger check on every price the order carries
+        if let Some(last_price) = last_price {
+            let band = last_price.scale(self.limits.price_band_percent, 100);
+
+            let prices = match &client_preference.order_type {
+                OrderType::Market => vec![],
+                OrderType::Limit { price } => vec![*price],
+                OrderType::Stop { stop_price } => vec![*stop_price],
+                OrderType::StopLimit { stop_price, limit_price } => vec![*stop_price, *limit_price],
+            };
+
+            if let Some(price) = prices.into_iter().find(|price| *price < last_price - band || *price > last_price + band) {
+                return Err(RiskRejection {
+                    rule: RiskRule::PriceBand,
+                    reason: format!("Price RM {} is more than {}% away from the last price of RM {}", price, self.limits.price_band_percent, last_price),
+                });
+            }
+        }
+
+        let order_price = match (&client_preference.order_type, last_price) {
+            (OrderType::Limit { price }, _) => *price,
+            (OrderType::StopLimit { limit_price, .. }, _) => *limit_price,
+            (OrderType::Stop { stop_price }, _) => *stop_price,
+            (OrderType::Market, Some(last_price)) => last_price,
+            (OrderType::Market, None) => Money::ZERO,
+        };
+
+        let order_value = order_price * client_preference.quantity;
+
+        if order_value > self.limits.max_order_value {
+            return Err(RiskRejection {
+                rule: RiskRule::MaxOrderValue,
+                reason: format!("Order value RM {} is above the RM {} limit", order_value, self.limits.max_order_value),
+            });
+        }
+
+        if is_buy {
+            // Shares held, filled ones on this order included, plus those the client's other open buys could still bring in
+            let held = account.positions.get(&client_preference.stock_symbol).map(|position| position.quantity).unwrap_or(0)
+                .saturating_add(account.reserved_buys(&client_preference.stock_symbol, &client_preference.order_id));
+            let remaining = client_preference.quantity.saturating_sub(filled);
+            let position = held.saturating_add(remaining);
+
+            if position > self.limits.max_position {
+                return Err(RiskRejection {
+                    rule: RiskRule::MaxPosition,
+                    reason: format!("Buying {} would take the {} position to {} shares including open buys, above the {} share limit", remaining, client_preference.stock_symbol, position, self.limits.max_position),
+                });
+            }
+
+            // Once the day's loss is used up the client may only sell down what they hold
+            let daily_loss = self.daily_loss(account, last_prices, opening_prices);
+
+            if daily_loss > self.limits.daily_loss_limit {
+                return Err(RiskRejection {
+                    rule: RiskRule::DailyLossLimit,
+                    reason: format!("Client {} has lost RM {} today, above the RM {} limit", account.client_number, daily_loss, self.limits.daily_loss_limit),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Realised P&L plus mark-to-market change since the exchange's trading day opened, zero if the client is up
+    // Holdings are valued at the day's opening prices when the client's first order of the day is checked
+    fn daily_loss(&mut self, account: &ClientAccount, last_prices: &HashMap<String, Money>, opening_prices: &HashMap<String, Money>) -> Money {
+        let day = self.clock.now().trading_day();
+
+        let trading_day = self.trading_days.entry(account.client_number.clone())
+            .or_insert_with(|| TradingDay { day, opening_equity: account.equity(opening_prices) });
+
+        if trading_day.day != day {
+            *trading_day = TradingDay { day, opening_equity: account.equity(opening_prices) };
+        }
+
+        (trading_day.opening_equity - account.equity(last_prices)).max(Money::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::INITIAL_HOLDING;
+    use crate::client::Decision;
+    use crate::order_book::TimeInForce;
+    use crate::simulation::{ClockMode, Simulation, SimulationConfig};
+    use crate::trend::TrendPattern;
+
+    fn buy(price: Money, quantity: u64) -> ClientStockPreference {
+        ClientStockPreference {
+            order_id: "order".to_string(),
+            client_number: "1".to_string(),
+            stock_symbol: "MYEG".to_string(),
+            min_price: price,
+            trend: TrendPattern::default(),
+            buy_sell_decision: Decision::Symbol,
+            indicator_condition: None,
+            trigger: None,
+            buy_or_sell: Side::Buy,
+            order_type: OrderType::Limit { price },
+            time_in_force: TimeInForce::Day,
+            quantity,
+            created_at: Timestamp::default(),
+        }
+    }
+
+    fn risk_manager(limits: RiskLimits) -> RiskManager {
+        let config = SimulationConfig { seed: Some(1), deterministic: true, clock: ClockMode::AsFastAsPossible, duration_secs: None };
+
+        RiskManager::new(limits, Simulation::new(&config).clock())
+    }
+
+    fn prices() -> HashMap<String, Money> {
+        HashMap::from([("MYEG".to_string(), Money::from_ringgit(10))])
+    }
+
+    #[test]
+    fn replace_of_a_partly_filled_buy_counts_its_fills_once() {
+        let mut risk_manager = risk_manager(RiskLimits { max_position: 5_000, ..RiskLimits::default() });
+        let mut account = ClientAccount::new("1".to_string(), &prices());
+
+        let order = buy(Money::from_ringgit(10), 5_000 - INITIAL_HOLDING);
+        account.reserve(&order, None).unwrap();
+        account.apply_fill("order", 1_000, Money::from_ringgit(10));
+
+        // Same total at a new price: 1000 shares are already in the position, the rest still reserved
+        let replaced = buy(Money::from_sen(1010), 5_000 - INITIAL_HOLDING);
+
+        assert!(risk_manager.check_order(&replaced, 1_000, &account, &prices(), &prices()).is_ok());
+
+        // Growing the order past the limit is still refused
+        let grown = buy(Money::from_sen(1010), 5_000 - INITIAL_HOLDING + 100);
+        let rejection = risk_manager.check_order(&grown, 1_000, &account, &prices(), &prices()).unwrap_err();
+
+        assert_eq!(rejection.rule, RiskRule::MaxPosition);
+    }
+
+    #[test]
+    fn refuses_a_huge_quantity_without_overflowing() {
+        let mut risk_manager = risk_manager(RiskLimits { max_order_value: Money::from_milli(i64::MAX), ..RiskLimits::default() });
+        let account = ClientAccount::new("1".to_string(), &prices());
+
+        let rejection = risk_manager.check_order(&buy(Money::from_ringgit(10), u64::MAX), 0, &account, &prices(), &prices()).unwrap_err();
+
+        assert_eq!(rejection.rule, RiskRule::MaxPosition);
+    }
+}
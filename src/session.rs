@@ -0,0 +1,101 @@
+use std::fmt;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::order_book::{OrderType, TimeInForce};
+use crate::simulation::Timestamp;
+
+// Phases of Bursa's trading day, in the order they happen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TradingSession {
+    PreOpening,
+    MorningSession,
+    LunchBreak,
+    AfternoonPreOpening,
+    AfternoonSession,
+    PreClosing,
+    Closed,
+}
+
+// When each session starts, measured from the 09:00 open of every simulated day
+// Pre-opening is shortened so a real-time run does not sit idle for half an hour
+pub const SESSION_SCHEDULE: [(Duration, TradingSession); 7] = [
+    (Duration::from_secs(0), TradingSession::PreOpening),                        // 09:00
+    (Duration::from_secs(15 * 60), TradingSession::MorningSession),              // 09:15
+    (Duration::from_secs(210 * 60), TradingSession::LunchBreak),                 // 12:30
+    (Duration::from_secs(300 * 60), TradingSession::AfternoonPreOpening),        // 14:00
+    (Duration::from_secs(330 * 60), TradingSession::AfternoonSession),           // 14:30
+    (Duration::from_secs(465 * 60), TradingSession::PreClosing),                 // 16:45
+    (Duration::from_secs(470 * 60), TradingSession::Closed),                     // 16:50
+];
+
+// Broadcast on the stock price fanout whenever the market moves into a new session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionChange {
+    pub exchange: String,
+    pub session: TradingSession,
+    pub at: Timestamp,
+}
+
+impl TradingSession {
+    // Session the market is in at a point of the simulated day
+    pub fn at(now: Timestamp) -> TradingSession {
+        let time_of_day = now.time_of_day();
+
+        SESSION_SCHEDULE.iter()
+            .rev()
+            .find(|(starts, _)| time_of_day >= *starts)
+            .map(|(_, session)| *session)
+            .unwrap_or(TradingSession::PreOpening)
+    }
+
+    // New orders and amendments are taken, matched or not
+    pub fn accepts_orders(&self) -> bool {
+        self.is_continuous() || self.is_call_phase()
+    }
+
+    // Orders are matched as they arrive
+    pub fn is_continuous(&self) -> bool {
+        matches!(self, TradingSession::MorningSession | TradingSession::AfternoonSession)
+    }
+
+    // Orders are collected without matching, then uncrossed in one auction when the session ends
+    pub fn is_call_phase(&self) -> bool {
+        matches!(self, TradingSession::PreOpening | TradingSession::AfternoonPreOpening | TradingSession::PreClosing)
+    }
+
+    // Reason a new or amended order cannot be entered in this session, if any
+    pub fn check_order(&self, order_type: &OrderType, time_in_force: TimeInForce) -> Option<String> {
+        if !self.accepts_orders() {
+            return Some(format!("Orders are not accepted during {}", self));
+        }
+
+        // Only priced orders that can wait take part in an auction
+        if self.is_call_phase() && *order_type == OrderType::Market {
+            return Some(format!("Market orders are not accepted during {}", self));
+        }
+
+        if self.is_call_phase() && matches!(time_in_force, TimeInForce::Ioc | TimeInForce::Fok) {
+            return Some(format!("IOC and FOK orders are not accepted during {}", self));
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for TradingSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TradingSession::PreOpening => "pre-opening",
+            TradingSession::MorningSession => "the morning session",
+            TradingSession::LunchBreak => "the lunch break",
+            TradingSession::AfternoonPreOpening => "the afternoon pre-opening",
+            TradingSession::AfternoonSession => "the afternoon session",
+            TradingSession::PreClosing => "pre-closing",
+            TradingSession::Closed => "the close",
+        };
+
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,532 @@
+use std::fmt;
+use std::thread;
+use std::sync::{Arc, Condvar, Mutex};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crossbeam_channel::Sender;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use scheduled_thread_pool::{OnPoolDropBehavior, ScheduledThreadPool};
+
+use crate::stock_exchange::TRADING_DAY_SECONDS;
+
+// Trading opens at 09:00 on every simulated day
+const MARKET_OPEN_SECONDS: u64 = 9 * 60 * 60;
+
+// Seed and scheduling mode of a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+    // A fresh seed is drawn and printed when not given, so any run can be repeated
+    pub seed: Option<u64>,
+    // Handle one message or timer at a time in a fixed order, needs the in-process transport
+    pub deterministic: bool,
+    // How fast simulated time runs against the wall clock
+    pub clock: ClockMode,
+    // Stop by itself after this much simulated time, otherwise run until Ctrl-C
+    pub duration_secs: Option<u64>,
+}
+
+// How simulated time relates to the wall clock
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ClockMode {
+    #[default]
+    RealTime,
+    // Simulated seconds per wall-clock second
+    Accelerated { speed: f64 },
+    // Jump straight to the next timer once everything is handled, needs deterministic scheduling
+    AsFastAsPossible,
+}
+
+impl ClockMode {
+    // None when nothing waits for the wall clock
+    fn speed(&self) -> Option<f64> {
+        match self {
+            ClockMode::RealTime => Some(1.0),
+            ClockMode::Accelerated { speed } => Some(*speed),
+            ClockMode::AsFastAsPossible => None,
+        }
+    }
+}
+
+impl fmt::Display for ClockMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockMode::RealTime => write!(f, "real time"),
+            ClockMode::Accelerated { speed } => write!(f, "{}x real time", speed),
+            ClockMode::AsFastAsPossible => write!(f, "as fast as possible"),
+        }
+    }
+}
+
+// Simulated time since the simulation started, to the millisecond
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub fn from_duration(duration: Duration) -> Timestamp {
+        Timestamp(duration.as_millis() as u64)
+    }
+
+    pub fn after(self, duration: Duration) -> Timestamp {
+        Timestamp(self.0 + duration.as_millis() as u64)
+    }
+
+    // Zero if earlier is actually later
+    pub fn since(self, earlier: Timestamp) -> Duration {
+        Duration::from_millis(self.0.saturating_sub(earlier.0))
+    }
+
+    // Trading days since the simulation started, counting from 0
+    pub fn trading_day(self) -> u64 {
+        self.0 / (TRADING_DAY_SECONDS * 1000)
+    }
+
+    // Time since the day's 09:00 open
+    pub fn time_of_day(self) -> Duration {
+        Duration::from_millis(self.0 % (TRADING_DAY_SECONDS * 1000))
+    }
+}
+
+// e.g. Day 1 09:00:04.000
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let into_day = self.time_of_day().as_millis() as u64 + MARKET_OPEN_SECONDS * 1000;
+        let seconds = into_day / 1000;
+
+        write!(f, "Day {} {:02}:{:02}:{:02}.{:03}", self.trading_day() + 1, seconds / 3600, seconds / 60 % 60, seconds % 60, into_day % 1000)
+    }
+}
+
+// Simulated time, shared by every component
+#[derive(Clone)]
+pub struct Clock {
+    started: Instant,
+    speed: Option<f64>,
+    // Deterministic runs keep their own time
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+impl Clock {
+    pub fn now(&self) -> Timestamp {
+        match (&self.scheduler, self.speed) {
+            (Some(scheduler), _) => Timestamp::from_duration(scheduler.state.lock().unwrap().now),
+            (None, Some(speed)) => Timestamp::from_duration(self.started.elapsed().mul_f64(speed)),
+            (None, None) => Timestamp::default(),
+        }
+    }
+
+    // Wall-clock time a simulated span takes
+    fn wall(&self, simulated: Duration) -> Duration {
+        simulated.div_f64(self.speed.unwrap_or(1.0))
+    }
+}
+
+// Handed to every component: where its randomness comes from and how it schedules work
+#[derive(Clone)]
+pub struct Simulation {
+    seed: u64,
+    duration: Option<Duration>,
+    clock: Clock,
+    // Only in deterministic mode
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+impl Simulation {
+    pub fn new(config: &SimulationConfig) -> Simulation {
+        let scheduler = config.deterministic.then(|| Arc::new(Scheduler::new(config.clock.speed())));
+
+        Simulation {
+            seed: config.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+            duration: config.duration_secs.map(Duration::from_secs),
+            clock: Clock { started: Instant::now(), speed: config.clock.speed(), scheduler: scheduler.clone() },
+            scheduler,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    pub fn scheduler(&self) -> Option<Arc<Scheduler>> {
+        self.scheduler.clone()
+    }
+
+    // Each component draws from its own stream, so one component's draws never shift another's
+    pub fn rng(&self, component: &str) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ fnv1a(component))
+    }
+
+    // Run a job every period of simulated time, stopped when the returned timer is dropped
+    pub fn every<F>(&self, name: &str, initial_delay: Duration, period: Duration, job: F) -> Timer
+    where
+        F: FnMut() + Send + 'static,
+    {
+        match &self.scheduler {
+            Some(scheduler) => Timer::Scheduled(scheduler.clone(), scheduler.add_timer(name, initial_delay, period, Box::new(job))),
+            None => {
+                let pool = ScheduledThreadPool::builder()
+                    .num_threads(1)
+                    .on_drop_behavior(OnPoolDropBehavior::DiscardPendingScheduled)
+                    .build();
+
+                pool.execute_at_fixed_rate(self.clock.wall(initial_delay), self.clock.wall(period), job);
+
+                Timer::Pool(pool)
+            }
+        }
+    }
+
+    // Hand work to another thread of the component, in turn with everything else in deterministic mode
+    pub fn send<T: Send + 'static>(&self, sender: &Sender<T>, value: T) {
+        match &self.scheduler {
+            Some(scheduler) => {
+                let sender = sender.clone();
+                scheduler.deliver(move || sender.send(value).is_ok());
+            }
+            None => {
+                let _ = sender.send(value);
+            }
+        }
+    }
+
+    // Taken for each message a component handles, the next one is delivered once it is dropped
+    pub fn handling(&self) -> Handling {
+        Handling { scheduler: self.scheduler.clone() }
+    }
+
+    // Called by the lifecycle controller for every component it spawns
+    pub fn expect_start(&self) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.state.lock().unwrap().starting += 1;
+        }
+    }
+
+    // A component has subscribed to everything it listens to
+    pub fn started(&self) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.state.lock().unwrap().starting -= 1;
+            scheduler.wake.notify_all();
+        }
+    }
+
+    // Begin delivering once every component has started
+    pub fn launch(&self) {
+        if let Some(scheduler) = &self.scheduler {
+            let scheduler = scheduler.clone();
+            thread::spawn(move || scheduler.dispatch());
+        }
+    }
+
+    // Shutting down, nothing is held back and simulated time stops
+    pub fn release(&self) {
+        if let Some(scheduler) = &self.scheduler {
+            let deliveries: Vec<Delivery> = {
+                let mut state = scheduler.state.lock().unwrap();
+                state.released = true;
+                state.handling = 0;
+                state.deliveries.drain(..).collect()
+            };
+
+            scheduler.wake.notify_all();
+
+            for delivery in deliveries {
+                delivery();
+            }
+        }
+    }
+}
+
+// Stable across runs and platforms, unlike the std hasher
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// Stops its job when dropped
+pub enum Timer {
+    // Only held so dropping the timer drops the pool
+    Pool(#[allow(dead_code)] ScheduledThreadPool),
+    Scheduled(Arc<Scheduler>, u64),
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Timer::Scheduled(scheduler, id) = self {
+            scheduler.state.lock().unwrap().timers.retain(|timer| timer.id != *id);
+        }
+    }
+}
+
+pub struct Handling {
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        if let Some(scheduler) = &self.scheduler {
+            let mut state = scheduler.state.lock().unwrap();
+            state.handling = state.handling.saturating_sub(1);
+            scheduler.wake.notify_all();
+        }
+    }
+}
+
+// Puts a message on its channel, false if nobody will handle it
+type Delivery = Box<dyn FnOnce() -> bool + Send>;
+
+struct ScheduledJob {
+    id: u64,
+    name: String,
+    // Simulated time since launch
+    due: Duration,
+    period: Duration,
+    // Taken out while the job runs
+    job: Option<Box<dyn FnMut() + Send>>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    // In the order they were sent
+    deliveries: VecDeque<Delivery>,
+    timers: Vec<ScheduledJob>,
+    next_timer_id: u64,
+    // Simulated time, moves to each timer as it fires
+    now: Duration,
+    // None runs as fast as possible
+    speed: Option<f64>,
+    // Components still subscribing
+    starting: usize,
+    // Messages delivered but not handled yet
+    handling: usize,
+    released: bool,
+}
+
+// Runs a deterministic simulation: one delivery or timer at a time, timers in order of (due, name)
+pub struct Scheduler {
+    state: Mutex<SchedulerState>,
+    wake: Condvar,
+}
+
+impl Scheduler {
+    fn new(speed: Option<f64>) -> Scheduler {
+        Scheduler {
+            state: Mutex::new(SchedulerState { speed, ..SchedulerState::default() }),
+            wake: Condvar::new(),
+        }
+    }
+
+    pub fn deliver<F>(&self, delivery: F)
+    where
+        F: FnOnce() -> bool + Send + 'static,
+    {
+        let mut state = self.state.lock().unwrap();
+
+        if state.released {
+            drop(state);
+            delivery();
+            return;
+        }
+
+        state.deliveries.push_back(Box::new(delivery));
+        self.wake.notify_all();
+    }
+
+    fn add_timer(&self, name: &str, initial_delay: Duration, period: Duration, job: Box<dyn FnMut() + Send>) -> u64 {
+        let mut state = self.state.lock().unwrap();
+
+        let id = state.next_timer_id;
+        state.next_timer_id += 1;
+
+        let due = state.now + initial_delay;
+        state.timers.push(ScheduledJob { id, name: name.to_string(), due, period, job: Some(job) });
+        self.wake.notify_all();
+
+        id
+    }
+
+    // Simulated time is paced by the wall clock unless running as fast as possible, the order of events never is
+    fn dispatch(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        while state.starting > 0 {
+            state = self.wake.wait(state).unwrap();
+        }
+
+        let launched = Instant::now();
+
+        loop {
+            if state.released {
+                return;
+            }
+
+            if state.handling > 0 {
+                state = self.wake.wait(state).unwrap();
+                continue;
+            }
+
+            if let Some(delivery) = state.deliveries.pop_front() {
+                state.handling += 1;
+                drop(state);
+
+                let delivered = delivery();
+
+                state = self.state.lock().unwrap();
+
+                if !delivered {
+                    state.handling = state.handling.saturating_sub(1);
+                }
+
+                continue;
+            }
+
+            let next = state.timers.iter()
+                .filter(|timer| timer.job.is_some())
+                .min_by(|a, b| (a.due, &a.name, a.id).cmp(&(b.due, &b.name, b.id)))
+                .map(|timer| (timer.id, timer.due));
+
+            let (id, due) = match next {
+                Some(next) => next,
+                None => {
+                    state = self.wake.wait(state).unwrap();
+                    continue;
+                }
+            };
+
+            if let Some(speed) = state.speed {
+                let wall_due = due.div_f64(speed);
+                let elapsed = launched.elapsed();
+
+                // Woken early if a message arrives or a timer is added
+                if wall_due > elapsed {
+                    state = self.wake.wait_timeout(state, wall_due - elapsed).unwrap().0;
+                    continue;
+                }
+            }
+
+            state.now = due;
+
+            let mut job = match state.timers.iter_mut().find(|timer| timer.id == id) {
+                Some(timer) => timer.job.take().unwrap(),
+                None => continue,
+            };
+
+            drop(state);
+            job();
+            state = self.state.lock().unwrap();
+
+            // Cancelled while it ran
+            if let Some(timer) = state.timers.iter_mut().find(|timer| timer.id == id) {
+                timer.due += timer.period;
+                timer.job = Some(job);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crossbeam_channel::{never, Receiver};
+
+    use crate::config::MarketConfig;
+    use crate::instrument::load_instruments;
+    use crate::money::Money;
+    use crate::order_book::ExecutionReport;
+    use crate::protocol;
+    use crate::transport::{InProcessTransport, Message, Result, Transport};
+    use super::Simulation;
+
+    // In-process transport that notes every fill the stock exchange reports
+    struct RecordingTransport {
+        inner: InProcessTransport,
+        exchange: String,
+        fills: Mutex<Vec<String>>,
+    }
+
+    impl Transport for RecordingTransport {
+        fn publish(&self, queue: &str, message: Message) -> Result<()> {
+            if let Ok(envelope) = protocol::decode::<ExecutionReport>(&message.body) {
+                let report = envelope.payload;
+
+                if envelope.sender == self.exchange && report.fill_quantity > 0 {
+                    self.fills.lock().unwrap().push(format!("{} {} {:?} {} @ RM {}",
+                        envelope.timestamp, report.order_id, report.buy_or_sell, report.fill_quantity, report.fill_price));
+                }
+            }
+
+            self.inner.publish(queue, message)
+        }
+
+        fn broadcast(&self, exchange: &str, body: &[u8]) -> Result<()> {
+            self.inner.broadcast(exchange, body)
+        }
+
+        fn consume(&self, queue: &str) -> Result<Receiver<Message>> {
+            self.inner.consume(queue)
+        }
+
+        fn subscribe(&self, exchange: &str, queue: &str) -> Result<Receiver<Message>> {
+            self.inner.subscribe(exchange, queue)
+        }
+
+        fn purge(&self, queue: &str) -> Result<()> {
+            self.inner.purge(queue)
+        }
+
+        fn close(&self) -> Result<()> {
+            self.inner.close()
+        }
+    }
+
+    // Fills of a short, busy run over a few stocks, in the order they were reported
+    fn fills(seed: u64) -> Vec<String> {
+        let mut config = MarketConfig::load("config/fast_forward.json").unwrap();
+        config.simulation.seed = Some(seed);
+        config.simulation.duration_secs = Some(20 * 60);
+
+        for client in config.clients.iter_mut() {
+            client.strategy.order_interval_secs = [2, 4];
+            client.strategy.min_price = Money::from_ringgit(95);
+            client.strategy.max_price = Money::from_ringgit(105);
+        }
+
+        let instruments = load_instruments(&config.exchange.instruments_path).unwrap().into_iter().take(3).collect();
+
+        let simulation = Simulation::new(&config.simulation);
+        let transport = Arc::new(RecordingTransport {
+            inner: InProcessTransport::new(simulation.scheduler()),
+            exchange: config.exchange.name.clone(),
+            fills: Mutex::new(Vec::new()),
+        });
+
+        // Nothing interrupts a test run, it stops when its time is up
+        crate::run(config, instruments, simulation, transport.clone(), never());
+
+        let fills = transport.fills.lock().unwrap().clone();
+        fills
+    }
+
+    #[test]
+    fn same_seed_gives_identical_trades() {
+        let first = fills(20240601);
+        let second = fills(20240601);
+
+        assert!(!first.is_empty(), "the run made no trades to compare");
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::instrument::Instrument;
+use crate::order_book::OrderType;
+
+// Bursa Malaysia trades in board lots of 100 shares
+pub const BOARD_LOT: u64 = 100;
+
+// Minimum price movement from a price upwards
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TickBand {
+    pub from: Money,
+    pub tick: Money,
+}
+
+// Bursa's tick table, used when the instrument master does not give one
+pub fn bursa_tick_table() -> Vec<TickBand> {
+    vec![
+        TickBand { from: Money::ZERO, tick: Money::from_milli(5) },
+        TickBand { from: Money::from_ringgit(1), tick: Money::from_sen(1) },
+        TickBand { from: Money::from_ringgit(10), tick: Money::from_sen(2) },
+        TickBand { from: Money::from_ringgit(100), tick: Money::from_sen(10) },
+    ]
+}
+
+pub fn tick_size(tick_table: &[TickBand], price: Money) -> Money {
+    tick_table.iter()
+        .rev()
+        .find(|band| price >= band.from)
+        .or(tick_table.first())
+        .map(|band| band.tick)
+        .unwrap_or(Money::from_sen(1))
+}
+
+// Nearest valid price for the band the price falls in
+pub fn round_to_tick(tick_table: &[TickBand], price: Money) -> Money {
+    price.round_to(tick_size(tick_table, price))
+}
+
+fn is_on_tick(tick_table: &[TickBand], price: Money) -> bool {
+    price.is_multiple_of(tick_size(tick_table, price))
+}
+
+// Reason the order breaks the instrument's lot or tick rules, if any
+pub fn check_order(instrument: &Instrument, order_type: &OrderType, quantity: u64) -> Option<String> {
+    if quantity == 0 || !quantity.is_multiple_of(instrument.lot_size) {
+        return Some(format!("Quantity {} is not a multiple of the board lot of {} shares", quantity, instrument.lot_size));
+    }
+
+    let prices = match order_type {
+        OrderType::Market => vec![],
+        OrderType::Limit { price } => vec![*price],
+        OrderType::Stop { stop_price } => vec![*stop_price],
+        OrderType::StopLimit { stop_price, limit_price } => vec![*stop_price, *limit_price],
+    };
+
+    prices.into_iter()
+        .find(|price| !is_on_tick(&instrument.tick_table, *price))
+        .map(|price| format!("Price RM {} is not a multiple of the RM {} tick size for its price band", price, instrument.tick_size(price)))
+}
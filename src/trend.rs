@@ -0,0 +1,218 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::money::Money;
+
+// Longest pattern a broker will watch for, it keeps one more price than this per stock
+pub const MAX_TREND_PATTERN_LENGTH: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepDirection {
+    Up,
+    Down,
+    // Either way, or not at all
+    Any,
+}
+
+// One price move of a pattern, e.g. UP, DOWN>=0.5% or ANY
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrendStep {
+    pub direction: StepDirection,
+    // Smallest move that counts, in percent of the previous price
+    pub min_move_percent: f64,
+}
+
+impl TrendStep {
+    pub fn new(direction: StepDirection, min_move_percent: f64) -> TrendStep {
+        TrendStep { direction, min_move_percent }
+    }
+
+    fn matches(&self, from: Money, to: Money) -> bool {
+        let move_percent = (to.to_f64() - from.to_f64()) / from.to_f64() * 100.0;
+
+        let direction_matches = match self.direction {
+            StepDirection::Up => to > from,
+            StepDirection::Down => to < from,
+            StepDirection::Any => true,
+        };
+
+        direction_matches && move_percent.abs() >= self.min_move_percent
+    }
+}
+
+impl fmt::Display for TrendStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            StepDirection::Up => "UP",
+            StepDirection::Down => "DOWN",
+            StepDirection::Any => "ANY",
+        };
+
+        if self.min_move_percent > 0.0 {
+            write!(f, "{}>={}%", direction, self.min_move_percent)
+        } else {
+            write!(f, "{}", direction)
+        }
+    }
+}
+
+impl FromStr for TrendStep {
+    type Err = String;
+
+    // UP, DOWN or ANY (also *), optionally followed by >= and a percentage
+    fn from_str(value: &str) -> Result<TrendStep, String> {
+        let (direction, min_move) = match value.split_once(">=") {
+            Some((direction, min_move)) => (direction.trim(), Some(min_move.trim())),
+            None => (value.trim(), None),
+        };
+
+        let direction = match direction.to_ascii_uppercase().as_str() {
+            "UP" => StepDirection::Up,
+            "DOWN" => StepDirection::Down,
+            "ANY" | "*" => StepDirection::Any,
+            _ => return Err(format!("{} is not UP, DOWN or ANY", direction)),
+        };
+
+        let min_move_percent = match min_move {
+            Some(min_move) => min_move.trim_end_matches('%').trim().parse::<f64>()
+                .ok()
+                .filter(|min_move| min_move.is_finite() && *min_move >= 0.0)
+                .ok_or_else(|| format!("{} is not a percentage of 0 or more", min_move))?,
+            None => 0.0,
+        };
+
+        Ok(TrendStep { direction, min_move_percent })
+    }
+}
+
+// Written as text, so ["UP", "DOWN"] still reads as a pattern
+impl Serialize for TrendStep {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TrendStep {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TrendStep, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+// Consecutive price moves, oldest first, e.g. three UPs of at least 1% each
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TrendPattern(pub Vec<TrendStep>);
+
+impl TrendPattern {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.0.is_empty() || self.0.len() > MAX_TREND_PATTERN_LENGTH {
+            return Err(format!("Trend pattern must have 1 to {} moves", MAX_TREND_PATTERN_LENGTH));
+        }
+
+        Ok(())
+    }
+
+    // Whether the latest moves of the prices, oldest first, follow the pattern
+    pub fn matches(&self, prices: &[Money]) -> bool {
+        if self.0.is_empty() || prices.len() <= self.0.len() {
+            return false;
+        }
+
+        prices[prices.len() - self.0.len() - 1..].windows(2)
+            .zip(self.0.iter())
+            .all(|(pair, step)| step.matches(pair[0], pair[1]))
+    }
+}
+
+impl fmt::Display for TrendPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<String> = self.0.iter().map(|step| step.to_string()).collect();
+
+        write!(f, "{}", steps.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(steps: &[&str]) -> TrendPattern {
+        TrendPattern(steps.iter().map(|step| step.parse().unwrap()).collect())
+    }
+
+    fn prices(sen: &[i64]) -> Vec<Money> {
+        sen.iter().map(|sen| Money::from_sen(*sen)).collect()
+    }
+
+    #[test]
+    fn matches_only_the_latest_moves() {
+        let up_up_down = pattern(&["UP", "UP", "DOWN"]);
+
+        assert!(up_up_down.matches(&prices(&[1000, 1010, 1020, 1015])));
+        // Older moves do not matter
+        assert!(up_up_down.matches(&prices(&[1100, 900, 1000, 1010, 1020, 1015])));
+        assert!(!up_up_down.matches(&prices(&[1000, 1010, 1020, 1015, 1010])));
+        // A flat move is neither up nor down
+        assert!(!up_up_down.matches(&prices(&[1000, 1010, 1010, 1005])));
+    }
+
+    #[test]
+    fn needs_one_more_price_than_moves() {
+        let up_up = pattern(&["UP", "UP"]);
+
+        assert!(!up_up.matches(&prices(&[1000, 1010])));
+        assert!(up_up.matches(&prices(&[1000, 1010, 1020])));
+        assert!(!TrendPattern::default().matches(&prices(&[1000, 1010])));
+    }
+
+    #[test]
+    fn lets_any_match_every_move() {
+        let any_then_up = pattern(&["ANY", "UP"]);
+
+        assert!(any_then_up.matches(&prices(&[1000, 990, 1000])));
+        assert!(any_then_up.matches(&prices(&[1000, 1000, 1010])));
+        assert!(any_then_up.matches(&prices(&[1000, 1010, 1020])));
+        assert!(!any_then_up.matches(&prices(&[1000, 1010, 1000])));
+    }
+
+    #[test]
+    fn counts_only_moves_of_at_least_the_minimum() {
+        let big_up_then_down = pattern(&["UP>=1%", "DOWN >= 1"]);
+
+        // Up 2%, down about 1.96%
+        assert!(big_up_then_down.matches(&prices(&[1000, 1020, 1000])));
+        // Up only 0.5%
+        assert!(!big_up_then_down.matches(&prices(&[1000, 1005, 980])));
+
+        // ANY with a minimum still needs the price to move that much, either way
+        let big_move = pattern(&["ANY>=1%"]);
+
+        assert!(big_move.matches(&prices(&[1000, 980])));
+        assert!(!big_move.matches(&prices(&[1000, 1000])));
+    }
+
+    #[test]
+    fn reads_and_writes_patterns_as_text() {
+        let read: TrendPattern = serde_json::from_str(r#"["up", "DOWN>=0.5%", "*"]"#).unwrap();
+
+        assert_eq!(read, TrendPattern(vec![
+            TrendStep::new(StepDirection::Up, 0.0),
+            TrendStep::new(StepDirection::Down, 0.5),
+            TrendStep::new(StepDirection::Any, 0.0),
+        ]));
+        assert_eq!(serde_json::to_string(&read).unwrap(), r#"["UP","DOWN>=0.5%","ANY"]"#);
+
+        assert!("SIDEWAYS".parse::<TrendStep>().is_err());
+        assert!("UP>=-1%".parse::<TrendStep>().is_err());
+        assert!("UP>=".parse::<TrendStep>().is_err());
+    }
+
+    #[test]
+    fn refuses_empty_and_overlong_patterns() {
+        assert!(TrendPattern::default().validate().is_err());
+        assert!(TrendPattern(vec![TrendStep::new(StepDirection::Up, 0.0); MAX_TREND_PATTERN_LENGTH]).validate().is_ok());
+        assert!(TrendPattern(vec![TrendStep::new(StepDirection::Up, 0.0); MAX_TREND_PATTERN_LENGTH + 1]).validate().is_err());
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::money::Money;
+use crate::candle::BarHistory;
+use crate::index::IndexValue;
+use crate::indicator::IndicatorCondition;
+use crate::simulation::Timestamp;
+use crate::trend::TrendPattern;
+
+// Limits on what a client may send, so one order cannot keep its broker busy
+const MAX_TRIGGER_DEPTH: usize = 8;
+const MAX_TRIGGER_CONDITIONS: usize = 32;
+
+// Clock time of the simulated day, written HH:MM or HH:MM:SS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    pub fn from_hms(hours: u32, minutes: u32, seconds: u32) -> TimeOfDay {
+        TimeOfDay(hours * 3600 + minutes * 60 + seconds)
+    }
+
+    // Timestamps count from the 09:00 open
+    pub fn of(at: Timestamp) -> TimeOfDay {
+        TimeOfDay((Duration::from_secs(9 * 60 * 60) + at.time_of_day()).as_secs() as u32)
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 3600, self.0 / 60 % 60)?;
+
+        if !self.0.is_multiple_of(60) {
+            write!(f, ":{:02}", self.0 % 60)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<TimeOfDay, String> {
+        let parts: Vec<&str> = value.trim().split(':').collect();
+
+        let numbers: Option<Vec<u32>> = parts.iter().map(|part| part.parse::<u32>().ok()).collect();
+
+        match numbers.as_deref() {
+            Some([hours, minutes]) if *hours < 24 && *minutes < 60 => Ok(TimeOfDay::from_hms(*hours, *minutes, 0)),
+            Some([hours, minutes, seconds]) if *hours < 24 && *minutes < 60 && *seconds < 60 => Ok(TimeOfDay::from_hms(*hours, *minutes, *seconds)),
+            _ => Err(format!("{} is not a time of day like 10:30", value)),
+        }
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TimeOfDay, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+// When a broker sends a client's order on, built up from AND, OR and NOT over market conditions
+// Ranges take above, below or both, both meaning in between
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    // No condition, sent as soon as the market allows
+    Always,
+    All(Vec<Trigger>),
+    Any(Vec<Trigger>),
+    Not(Box<Trigger>),
+    // The stock's last price
+    Price {
+        #[serde(default)]
+        above: Option<Money>,
+        #[serde(default)]
+        below: Option<Money>,
+    },
+    Trend(TrendPattern),
+    Indicator(IndicatorCondition),
+    // Clock time of the simulated day, from inclusive and to exclusive
+    TimeWindow { from: TimeOfDay, to: TimeOfDay },
+    // Index points, the headline index when no index is named
+    IndexLevel {
+        #[serde(default)]
+        index: Option<String>,
+        #[serde(default)]
+        above: Option<f64>,
+        #[serde(default)]
+        below: Option<f64>,
+    },
+    // Index change since the previous close, in percent
+    IndexChange {
+        #[serde(default)]
+        index: Option<String>,
+        #[serde(default)]
+        above: Option<f64>,
+        #[serde(default)]
+        below: Option<f64>,
+    },
+}
+
+// What the broker knows when it checks a trigger
+pub struct TriggerContext<'a> {
+    pub stock_symbol: &'a str,
+    pub price: Money,
+    // Oldest first
+    pub recent_prices: &'a [Money],
+    pub bar_history: &'a BarHistory,
+    pub indices: &'a BTreeMap<String, IndexValue>,
+    pub now: Timestamp,
+}
+
+impl TriggerContext<'_> {
+    fn index(&self, index: &Option<String>) -> Option<&IndexValue> {
+        match index {
+            Some(index) => self.indices.get(index),
+            None => self.indices.values().find(|index_value| index_value.headline),
+        }
+    }
+}
+
+impl Trigger {
+    // Reason the expression can never be evaluated properly, if there is one
+    pub fn validate(&self) -> Result<(), String> {
+        if self.depth() > MAX_TRIGGER_DEPTH {
+            return Err(format!("trigger is nested more than {} levels deep", MAX_TRIGGER_DEPTH));
+        }
+
+        if self.conditions() > MAX_TRIGGER_CONDITIONS {
+            return Err(format!("trigger has more than {} conditions", MAX_TRIGGER_CONDITIONS));
+        }
+
+        self.validate_node()
+    }
+
+    fn validate_node(&self) -> Result<(), String> {
+        match self {
+            Trigger::Always => Ok(()),
+            Trigger::All(triggers) | Trigger::Any(triggers) => {
+                if triggers.is_empty() {
+                    return Err("all and any need at least one condition".to_string());
+                }
+
+                triggers.iter().try_for_each(|trigger| trigger.validate_node())
+            }
+            Trigger::Not(trigger) => trigger.validate_node(),
+            Trigger::Price { above, below } => {
+                if above.is_some_and(|price| price <= Money::ZERO) || below.is_some_and(|price| price <= Money::ZERO) {
+                    return Err("price bounds must be positive".to_string());
+                }
+
+                validate_range("price", above.map(Money::to_f64), below.map(Money::to_f64))
+            }
+            Trigger::Trend(pattern) => pattern.validate(),
+            Trigger::Indicator(condition) => condition.validate(),
+            Trigger::TimeWindow { from, to } => {
+                if from >= to {
+                    return Err(format!("time window from {} must be before to {}", from, to));
+                }
+
+                Ok(())
+            }
+            Trigger::IndexLevel { above, below, .. } => validate_range("index level", *above, *below),
+            Trigger::IndexChange { above, below, .. } => validate_range("index change", *above, *below),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            Trigger::All(triggers) | Trigger::Any(triggers) => 1 + triggers.iter().map(|trigger| trigger.depth()).max().unwrap_or(0),
+            Trigger::Not(trigger) => 1 + trigger.depth(),
+            _ => 1,
+        }
+    }
+
+    fn conditions(&self) -> usize {
+        match self {
+            Trigger::All(triggers) | Trigger::Any(triggers) => triggers.iter().map(|trigger| trigger.conditions()).sum(),
+            Trigger::Not(trigger) => trigger.conditions(),
+            _ => 1,
+        }
+    }
+
+    // Conditions on data the broker does not have yet are not met
+    pub fn is_met(&self, context: &TriggerContext) -> bool {
+        match self {
+            Trigger::Always => true,
+            Trigger::All(triggers) => triggers.iter().all(|trigger| trigger.is_met(context)),
+            Trigger::Any(triggers) => triggers.iter().any(|trigger| trigger.is_met(context)),
+            Trigger::Not(trigger) => !trigger.is_met(context),
+            Trigger::Price { above, below } => in_range(context.price.to_f64(), above.map(Money::to_f64), below.map(Money::to_f64)),
+            Trigger::Trend(pattern) => pattern.matches(context.recent_prices),
+            Trigger::Indicator(condition) => condition.is_met(context.bar_history, context.stock_symbol),
+            Trigger::TimeWindow { from, to } => {
+                let time_of_day = TimeOfDay::of(context.now);
+                *from <= time_of_day && time_of_day < *to
+            }
+            Trigger::IndexLevel { index, above, below } => context.index(index).is_some_and(|index_value| in_range(index_value.value, *above, *below)),
+            Trigger::IndexChange { index, above, below } => context.index(index).is_some_and(|index_value| in_range(index_value.change_percent, *above, *below)),
+        }
+    }
+}
+
+fn validate_range(name: &str, above: Option<f64>, below: Option<f64>) -> Result<(), String> {
+    match (above, below) {
+        (None, None) => Err(format!("{} needs above, below or both", name)),
+        (Some(above), Some(below)) if above >= below => Err(format!("{} above {} and below {} can never both hold", name, above, below)),
+        _ if above.is_some_and(|above| !above.is_finite()) || below.is_some_and(|below| !below.is_finite()) => Err(format!("{} bounds must be numbers", name)),
+        _ => Ok(()),
+    }
+}
+
+fn in_range(value: f64, above: Option<f64>, below: Option<f64>) -> bool {
+    above.is_none_or(|above| value > above) && below.is_none_or(|below| value < below)
+}
+
+fn describe_range(above: Option<String>, below: Option<String>) -> String {
+    match (above, below) {
+        (Some(above), Some(below)) => format!("between {} and {}", above, below),
+        (Some(above), None) => format!("above {}", above),
+        (None, Some(below)) => format!("below {}", below),
+        (None, None) => String::new(),
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |triggers: &Vec<Trigger>, separator: &str| -> String {
+            let triggers: Vec<String> = triggers.iter().map(|trigger| trigger.to_string()).collect();
+            format!("({})", triggers.join(separator))
+        };
+
+        let index_name = |index: &Option<String>| index.clone().unwrap_or_else(|| "headline index".to_string());
+
+        match self {
+            Trigger::Always => write!(f, "always"),
+            Trigger::All(triggers) => write!(f, "{}", join(triggers, " AND ")),
+            Trigger::Any(triggers) => write!(f, "{}", join(triggers, " OR ")),
+            Trigger::Not(trigger) => write!(f, "NOT {}", trigger),
+            Trigger::Price { above, below } => write!(f, "price {}", describe_range(above.map(|price| price.to_string()), below.map(|price| price.to_string()))),
+            Trigger::Trend(pattern) => write!(f, "trend {}", pattern),
+            Trigger::Indicator(condition) => write!(f, "{}", condition),
+            Trigger::TimeWindow { from, to } => write!(f, "from {} to {}", from, to),
+            Trigger::IndexLevel { index, above, below } => write!(f, "{} {}", index_name(index), describe_range(above.map(|level| level.to_string()), below.map(|level| level.to_string()))),
+            Trigger::IndexChange { index, above, below } => write!(f, "{} change {}", index_name(index), describe_range(above.map(|percent| format!("{}%", percent)), below.map(|percent| format!("{}%", percent)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::{Comparison, Operand};
+
+    fn nested(depth: usize) -> Trigger {
+        (1..depth).fold(Trigger::Always, |trigger, _| Trigger::Not(Box::new(trigger)))
+    }
+
+    fn headline(change_percent: f64) -> BTreeMap<String, IndexValue> {
+        let index_value = IndexValue {
+            exchange: "Bursa Malaysia".to_string(),
+            index: "KLCI".to_string(),
+            headline: true,
+            value: 1_600.0,
+            previous_close: 1_600.0 / (1.0 + change_percent / 100.0),
+            change_percent,
+            at: Timestamp::default(),
+        };
+
+        BTreeMap::from([("KLCI".to_string(), index_value)])
+    }
+
+    fn price(above: Option<i64>, below: Option<i64>) -> Trigger {
+        Trigger::Price { above: above.map(Money::from_sen), below: below.map(Money::from_sen) }
+    }
+
+    #[test]
+    fn refuses_triggers_nested_too_deep_or_with_too_many_conditions() {
+        assert!(nested(MAX_TRIGGER_DEPTH).validate().is_ok());
+        assert!(nested(MAX_TRIGGER_DEPTH + 1).validate().is_err());
+
+        assert!(Trigger::Any(vec![Trigger::Always; MAX_TRIGGER_CONDITIONS]).validate().is_ok());
+        assert!(Trigger::Any(vec![Trigger::Always; MAX_TRIGGER_CONDITIONS + 1]).validate().is_err());
+    }
+
+    #[test]
+    fn refuses_conditions_that_can_never_be_checked() {
+        assert!(Trigger::All(Vec::new()).validate().is_err());
+        assert!(price(None, None).validate().is_err());
+        assert!(price(Some(1000), Some(990)).validate().is_err());
+        assert!(price(Some(0), None).validate().is_err());
+        assert!(Trigger::TimeWindow { from: TimeOfDay::from_hms(11, 0, 0), to: TimeOfDay::from_hms(10, 0, 0) }.validate().is_err());
+        assert!(Trigger::IndexChange { index: None, above: Some(f64::NAN), below: None }.validate().is_err());
+
+        // Validation reaches conditions inside the expression
+        let indicator = IndicatorCondition::new(Operand::Sma { period: 0 }, Comparison::Above, Operand::Price);
+        assert!(Trigger::Not(Box::new(Trigger::Indicator(indicator))).validate().is_err());
+    }
+
+    #[test]
+    fn refuses_unknown_conditions_and_operands() {
+        let known = r#"{"all": [{"price": {"below": "10.00"}}, {"indicator": {"left": {"rsi": {"period": 14}}, "comparison": "below", "right": {"value": 30.0}}}]}"#;
+        assert!(serde_json::from_str::<Trigger>(known).unwrap().validate().is_ok());
+
+        let unknown_operand = r#"{"indicator": {"left": {"stochastic": {"period": 14}}, "comparison": "below", "right": {"value": 20.0}}}"#;
+        assert!(serde_json::from_str::<Trigger>(unknown_operand).is_err());
+
+        let unknown_comparison = r#"{"indicator": {"left": "price", "comparison": "near", "right": "vwap"}}"#;
+        assert!(serde_json::from_str::<Trigger>(unknown_comparison).is_err());
+
+        assert!(serde_json::from_str::<Trigger>(r#"{"volume": {"above": 1000}}"#).is_err());
+    }
+
+    #[test]
+    fn evaluates_conditions_against_what_the_broker_knows() {
+        let bar_history = BarHistory::new(10);
+        let indices = headline(1.5);
+
+        let context = TriggerContext {
+            stock_symbol: "MYEG",
+            price: Money::from_sen(1000),
+            recent_prices: &[],
+            bar_history: &bar_history,
+            indices: &indices,
+            // 10:30
+            now: Timestamp::from_duration(Duration::from_secs(90 * 60)),
+        };
+
+        let morning = Trigger::TimeWindow { from: TimeOfDay::from_hms(10, 0, 0), to: TimeOfDay::from_hms(10, 30, 0) };
+        let rising_market = Trigger::IndexChange { index: None, above: Some(1.0), below: None };
+
+        assert!(Trigger::Always.is_met(&context));
+        assert!(price(Some(990), Some(1010)).is_met(&context));
+        assert!(!price(Some(1000), None).is_met(&context));
+        assert!(rising_market.is_met(&context));
+        assert!(Trigger::IndexLevel { index: Some("KLCI".to_string()), above: Some(1_500.0), below: None }.is_met(&context));
+
+        // To is exclusive
+        assert!(!morning.is_met(&context));
+
+        assert!(Trigger::All(vec![price(None, Some(1010)), rising_market.clone()]).is_met(&context));
+        assert!(!Trigger::All(vec![price(None, Some(1010)), morning.clone()]).is_met(&context));
+        assert!(Trigger::Any(vec![morning.clone(), rising_market]).is_met(&context));
+        assert!(Trigger::Not(Box::new(morning)).is_met(&context));
+    }
+
+    #[test]
+    fn does_not_meet_conditions_on_data_the_broker_does_not_have() {
+        let bar_history = BarHistory::new(10);
+        let indices = BTreeMap::new();
+
+        let context = TriggerContext {
+            stock_symbol: "MYEG",
+            price: Money::from_sen(1000),
+            recent_prices: &[],
+            bar_history: &bar_history,
+            indices: &indices,
+            now: Timestamp::default(),
+        };
+
+        assert!(!Trigger::IndexChange { index: None, above: None, below: Some(100.0) }.is_met(&context));
+        assert!(!Trigger::IndexLevel { index: Some("KLCI".to_string()), above: Some(0.0), below: None }.is_met(&context));
+        assert!(!Trigger::Indicator(IndicatorCondition::new(Operand::Price, Comparison::Above, Operand::Value(0.0))).is_met(&context));
+    }
+}